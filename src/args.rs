@@ -0,0 +1,238 @@
+/// What an option takes after it.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Flag,
+    // a value, named in the help, e.g. -A NUM or --after-context=NUM
+    Value(&'static str),
+    // a value that can be left out, which then has to be given as
+    // --name=value, e.g. --color[=WHEN]
+    OptionalValue(&'static str),
+}
+
+/// An option of a command line, for `parse_args` and `help`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opt {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub arity: Arity,
+    // an option without help is not listed, e.g. an alternative spelling
+    pub help: &'static str,
+}
+
+impl Opt {
+    pub const fn new(
+        short: Option<char>,
+        long: &'static str,
+        arity: Arity,
+        help: &'static str,
+    ) -> Self {
+        Self {
+            short,
+            long,
+            arity,
+            help,
+        }
+    }
+}
+
+/// An argument of a command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// An option, by its long name, with its value if it takes one.
+    Opt(&'static str, Option<String>),
+    Operand(String),
+}
+
+/// Parses `args` (without the program name) as in GNU tools: short options
+/// can be combined (`-rn`) and take their value attached or as the next
+/// argument (`-C2`, `-C 2`), long options take it after `=` or as the next
+/// argument, and options may come after operands. `-` is an operand, and
+/// everything after `--` is too.
+pub fn parse_args(opts: &[Opt], args: &[String]) -> Result<Vec<Arg>, String> {
+    let mut parsed = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.by_ref().map(|arg| Arg::Operand(arg.clone())));
+        } else if let Some(option) = arg.strip_prefix("--") {
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            let opt = opts
+                .iter()
+                .find(|opt| opt.long == name)
+                .ok_or_else(|| format!("unrecognized option '{}'", arg))?;
+            let value = match (opt.arity, value) {
+                (Arity::Flag, Some(_)) => {
+                    return Err(format!("option '--{}' doesn't allow an argument", name))
+                }
+                (Arity::Value(_), None) => Some(
+                    args.next()
+                        .ok_or_else(|| format!("option '--{}' requires an argument", name))?
+                        .clone(),
+                ),
+                (_, value) => value,
+            };
+            parsed.push(Arg::Opt(opt.long, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for (i, c) in arg.char_indices().skip(1) {
+                let opt = opts
+                    .iter()
+                    .find(|opt| opt.short == Some(c))
+                    .ok_or_else(|| format!("invalid option -- '{}'", c))?;
+                if !matches!(opt.arity, Arity::Value(_)) {
+                    parsed.push(Arg::Opt(opt.long, None));
+                    continue;
+                }
+                // the rest of the argument is the value
+                let value = match &arg[i + c.len_utf8()..] {
+                    "" => args
+                        .next()
+                        .ok_or_else(|| format!("option requires an argument -- '{}'", c))?
+                        .clone(),
+                    rest => rest.to_string(),
+                };
+                parsed.push(Arg::Opt(opt.long, Some(value)));
+                break;
+            }
+        } else {
+            parsed.push(Arg::Operand(arg.clone()));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Returns the list of `opts` for `--help`, one per line, with the help
+/// texts aligned.
+pub fn help(opts: &[Opt]) -> String {
+    let names: Vec<String> = opts
+        .iter()
+        .map(|opt| {
+            let short = match opt.short {
+                Some(c) => format!("-{}, ", c),
+                None => "    ".to_string(),
+            };
+            let value = match opt.arity {
+                Arity::Flag => String::new(),
+                Arity::Value(name) => format!("={}", name),
+                Arity::OptionalValue(name) => format!("[={}]", name),
+            };
+            format!("{}--{}{}", short, opt.long, value)
+        })
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    opts.iter()
+        .zip(&names)
+        .filter(|(opt, _)| !opt.help.is_empty())
+        .map(|(opt, name)| format!("  {:width$}  {}\n", name, opt.help, width = width))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTS: &[Opt] = &[
+        Opt::new(Some('n'), "line-number", Arity::Flag, "print line numbers"),
+        Opt::new(Some('r'), "recursive", Arity::Flag, "search directories"),
+        Opt::new(Some('e'), "regexp", Arity::Value("PATTERN"), "use PATTERN"),
+        Opt::new(None, "color", Arity::OptionalValue("WHEN"), "use colors"),
+        Opt::new(None, "colour", Arity::OptionalValue("WHEN"), ""),
+    ];
+
+    fn parse(args: &[&str]) -> Result<Vec<Arg>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(OPTS, &args)
+    }
+
+    fn opt(long: &'static str, value: Option<&str>) -> Arg {
+        Arg::Opt(long, value.map(str::to_string))
+    }
+
+    fn operand(operand: &str) -> Arg {
+        Arg::Operand(operand.to_string())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse(&["-n", "a", "--recursive", "b"]),
+            Ok(vec![
+                opt("line-number", None),
+                operand("a"),
+                opt("recursive", None),
+                operand("b")
+            ])
+        );
+        assert_eq!(
+            parse(&["-rn"]),
+            Ok(vec![opt("recursive", None), opt("line-number", None)])
+        );
+        assert_eq!(
+            parse(&["-e", "-x", "-ne-y", "--regexp=-z", "--regexp", "--"]),
+            Ok(vec![
+                opt("regexp", Some("-x")),
+                opt("line-number", None),
+                opt("regexp", Some("-y")),
+                opt("regexp", Some("-z")),
+                opt("regexp", Some("--"))
+            ])
+        );
+        assert_eq!(
+            parse(&["--color", "--colour=always", "--color="]),
+            Ok(vec![
+                opt("color", None),
+                opt("colour", Some("always")),
+                opt("color", Some(""))
+            ])
+        );
+        assert_eq!(
+            parse(&["-n", "--", "-r", "--color", "--"]),
+            Ok(vec![
+                opt("line-number", None),
+                operand("-r"),
+                operand("--color"),
+                operand("--")
+            ])
+        );
+        assert_eq!(parse(&["-"]), Ok(vec![operand("-")]));
+        assert_eq!(parse(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_parse_args_error() {
+        assert_eq!(parse(&["-z"]), Err("invalid option -- 'z'".to_string()));
+        assert_eq!(parse(&["-nz"]), Err("invalid option -- 'z'".to_string()));
+        assert_eq!(
+            parse(&["--line"]),
+            Err("unrecognized option '--line'".to_string())
+        );
+        assert_eq!(
+            parse(&["-e"]),
+            Err("option requires an argument -- 'e'".to_string())
+        );
+        assert_eq!(
+            parse(&["--regexp"]),
+            Err("option '--regexp' requires an argument".to_string())
+        );
+        assert_eq!(
+            parse(&["--recursive=yes"]),
+            Err("option '--recursive' doesn't allow an argument".to_string())
+        );
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(
+            help(OPTS),
+            concat!(
+                "  -n, --line-number     print line numbers\n",
+                "  -r, --recursive       search directories\n",
+                "  -e, --regexp=PATTERN  use PATTERN\n",
+                "      --color[=WHEN]    use colors\n",
+            )
+        );
+    }
+}
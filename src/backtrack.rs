@@ -0,0 +1,700 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::charset::CharSet;
+use crate::parser::{Assertion, LookAround, Node};
+
+// A backtracking engine. The AST is compiled into a small program which is
+// run with an explicit stack of backtrack points, one start position at a
+// time. It can support constructs that need to cut or inspect the
+// backtracking, such as atomic groups, possessive quantifiers and
+// backreferences.
+//
+// Each (pc, position) pair that has been explored is remembered, so that a
+// search takes at most (number of instructions) * (input length) steps, times
+// the ways the loops around pc may or may not have progressed in their
+// current iteration, which is part of the key. This does not hold with
+// backreferences, where the same pair can lead to a different result
+// depending on what was captured, nor with atomic groups, where it depends on
+// which alternatives the group has cut, so such programs run without
+// memoization and are bounded by STEP_LIMIT instead.
+
+const STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    Char(char),
+    AnyChar,
+    CharClass(CharSet),
+    Assertion(Assertion),
+    // index into `Program::look_arounds`
+    LookAround(usize),
+    // match the text captured by the group again
+    Backreference(usize),
+    // try the first target, and the second one on backtracking
+    Split(usize, usize),
+    Jmp(usize),
+    // save the current position into a register
+    SavePos(usize),
+    // jump to the target if the current position equals the register, so
+    // that a loop stops after an iteration that matched nothing, as in Perl
+    CheckProgress(usize, usize),
+    AtomicStart,
+    AtomicEnd,
+    Match,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    insts: Vec<Inst>,
+    // capture groups are numbered from 1, and group n saves its start and
+    // end into the registers 2(n-1) and 2(n-1)+1
+    groups: usize,
+    registers: usize,
+    look_arounds: Vec<LookAroundProgram>,
+    // for each instruction, the registers of the loops it is in
+    loops: Vec<Vec<usize>>,
+    memoize: bool,
+}
+
+// the body of a lookaround, run as a separate program
+#[derive(Debug)]
+struct LookAroundProgram {
+    kind: LookAround,
+    program: Program,
+    min_len: usize,
+    max_len: Option<usize>,
+}
+
+pub fn compile(node: &Node) -> Result<Program, String> {
+    let mut groups = HashMap::new();
+    let mut names = HashMap::new();
+    number_groups(node, &mut groups, &mut names)?;
+    compile_program(node, &groups, &names)
+}
+
+// numbers the capture groups in the order of their opening parenthesis,
+// keyed by the address of their node
+fn number_groups(
+    node: &Node,
+    groups: &mut HashMap<*const Node, usize>,
+    names: &mut HashMap<String, usize>,
+) -> Result<(), String> {
+    match node {
+        Node::Group(_) => {
+            groups.insert(node as *const Node, groups.len() + 1);
+        }
+        Node::NamedGroup(name, _) => {
+            let group = groups.len() + 1;
+            groups.insert(node as *const Node, group);
+            if names.insert(name.clone(), group).is_some() {
+                return Err(format!("Duplicate group name: {}", name));
+            }
+        }
+        _ => {}
+    }
+    for child in node.children() {
+        number_groups(child, groups, names)?;
+    }
+    Ok(())
+}
+
+fn compile_program(
+    node: &Node,
+    groups: &HashMap<*const Node, usize>,
+    names: &HashMap<String, usize>,
+) -> Result<Program, String> {
+    let mut compiler = Compiler {
+        insts: vec![],
+        registers: groups.len() * 2,
+        look_arounds: vec![],
+        loops: vec![],
+        inst_loops: vec![],
+        groups,
+        names,
+    };
+    compiler.compile(node, true)?;
+    compiler.push(Inst::Match);
+    // the loops of an instruction are keyed as bits of a u64
+    let too_deep = compiler.inst_loops.iter().any(|loops| loops.len() > 64);
+    Ok(Program {
+        insts: compiler.insts,
+        groups: groups.len(),
+        registers: compiler.registers,
+        look_arounds: compiler.look_arounds,
+        loops: compiler.inst_loops,
+        memoize: !too_deep
+            && !node.any(&|node| {
+                matches!(
+                    node,
+                    Node::Backreference(_) | Node::NamedBackreference(_) | Node::Atomic(_)
+                )
+            }),
+    })
+}
+
+struct Compiler<'a> {
+    insts: Vec<Inst>,
+    registers: usize,
+    look_arounds: Vec<LookAroundProgram>,
+    // the registers of the loops being compiled, outermost first
+    loops: Vec<usize>,
+    inst_loops: Vec<Vec<usize>>,
+    groups: &'a HashMap<*const Node, usize>,
+    names: &'a HashMap<String, usize>,
+}
+
+impl Compiler<'_> {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.inst_loops.push(self.loops.clone());
+        self.insts.len() - 1
+    }
+
+    // emits a split whose targets are filled in later by `patch_split`
+    fn push_split(&mut self) -> usize {
+        self.push(Inst::Split(0, 0))
+    }
+
+    fn patch_split(&mut self, at: usize, preferred: usize, other: usize, greedy: bool) {
+        self.insts[at] = if greedy {
+            Inst::Split(preferred, other)
+        } else {
+            Inst::Split(other, preferred)
+        };
+    }
+
+    fn compile(&mut self, node: &Node, greedy: bool) -> Result<(), String> {
+        let key = node as *const Node;
+        match node {
+            Node::Literal(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::AnyChar => {
+                self.push(Inst::AnyChar);
+            }
+            Node::CharClass(set) => {
+                self.push(Inst::CharClass(set.clone()));
+            }
+            Node::Assertion(assertion) => {
+                self.push(Inst::Assertion(*assertion));
+            }
+            Node::LookAround(kind, node) => {
+                let (min_len, max_len) = node.length_range();
+                self.look_arounds.push(LookAroundProgram {
+                    kind: *kind,
+                    program: compile_program(node, self.groups, self.names)?,
+                    min_len,
+                    max_len,
+                });
+                self.push(Inst::LookAround(self.look_arounds.len() - 1));
+            }
+            Node::Backreference(group) => {
+                if *group == 0 || *group > self.groups.len() {
+                    return Err(format!("Invalid backreference: \\{}", group));
+                }
+                self.push(Inst::Backreference(*group));
+            }
+            Node::NamedBackreference(name) => {
+                let group = self
+                    .names
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown group name: {}", name))?;
+                self.push(Inst::Backreference(*group));
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node, true)?;
+                }
+            }
+            Node::Empty => {}
+            Node::Alternation(nodes) => {
+                // split L1 next; L1: first; jmp end; next: split L2 next2; ...; last; end:
+                let (last, nodes) = nodes.split_last().unwrap();
+                let mut jmps = vec![];
+                for node in nodes {
+                    let split = self.push_split();
+                    self.compile(node, true)?;
+                    jmps.push(self.push(Inst::Jmp(0)));
+                    let next = self.insts.len();
+                    self.patch_split(split, split + 1, next, true);
+                }
+                self.compile(last, true)?;
+                let end = self.insts.len();
+                for jmp in jmps {
+                    self.insts[jmp] = Inst::Jmp(end);
+                }
+            }
+            Node::Group(node) | Node::NamedGroup(_, node) => {
+                let group = self.groups[&key];
+                self.push(Inst::SavePos((group - 1) * 2));
+                self.compile(node, true)?;
+                self.push(Inst::SavePos((group - 1) * 2 + 1));
+            }
+            Node::ZeroOrMore(node) => self.compile_star(node, greedy)?,
+            Node::OneOrMore(node) => {
+                self.compile(node, true)?;
+                self.compile_star(node, greedy)?;
+            }
+            Node::ZeroOrOne(node) => self.compile_optional(node, greedy)?,
+            Node::Repeat(node, min, max) => {
+                for _ in 0..*min {
+                    self.compile(node, true)?;
+                }
+                match max {
+                    Some(max) => {
+                        for _ in *min..*max {
+                            self.compile_optional(node, greedy)?;
+                        }
+                    }
+                    None => self.compile_star(node, greedy)?,
+                }
+            }
+            Node::Lazy(node) => self.compile(node, false)?,
+            Node::Atomic(node) => {
+                self.push(Inst::AtomicStart);
+                self.compile(node, true)?;
+                self.push(Inst::AtomicEnd);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_star(&mut self, node: &Node, greedy: bool) -> Result<(), String> {
+        // L: split L1 end; L1: save r; node; check r end; jmp L; end:
+        let register = self.registers;
+        self.registers += 1;
+        let split = self.push_split();
+        self.loops.push(register);
+        self.push(Inst::SavePos(register));
+        self.compile(node, true)?;
+        let check = self.push(Inst::CheckProgress(register, 0));
+        self.push(Inst::Jmp(split));
+        self.loops.pop();
+        let end = self.insts.len();
+        self.insts[check] = Inst::CheckProgress(register, end);
+        self.patch_split(split, split + 1, end, greedy);
+        Ok(())
+    }
+
+    fn compile_optional(&mut self, node: &Node, greedy: bool) -> Result<(), String> {
+        // split L1 end; L1: node; end:
+        let split = self.push_split();
+        self.compile(node, true)?;
+        let end = self.insts.len();
+        self.patch_split(split, split + 1, end, greedy);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Frame {
+    // resume at pc and position
+    Alternative(usize, usize),
+    // restore a register to its previous value
+    Restore(usize, usize),
+    // marks where an atomic group started
+    AtomicBarrier,
+}
+
+/// Finds the leftmost match in `input` and returns its byte range.
+pub fn find_backtrack(program: &Program, input: &str) -> Result<Option<(usize, usize)>, String> {
+    find_backtrack_at(program, input, 0)
+}
+
+/// Like `find_backtrack`, but only finds matches that start at or after the
+/// byte offset `start`.
+pub fn find_backtrack_at(
+    program: &Program,
+    input: &str,
+    start: usize,
+) -> Result<Option<(usize, usize)>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let offsets: Vec<usize> = input
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(input.len()))
+        .collect();
+    let mut context = Context {
+        chars: &chars,
+        steps: 0,
+    };
+    // a pair that failed from one start fails from every start
+    let mut visited = HashSet::new();
+    let first = offsets.partition_point(|&pos| pos < start);
+    for start in first..=chars.len() {
+        if let Some(end) = context.run(program, start, None, &[], &mut visited)? {
+            return Ok(Some((offsets[start], offsets[end])));
+        }
+    }
+    Ok(None)
+}
+
+// the state shared by all runs of a search
+struct Context<'a> {
+    chars: &'a [char],
+    steps: usize,
+}
+
+impl Context<'_> {
+    // runs the program anchored at `start` and returns the end of the first
+    // match (which must be `end`, if given). `captures` are the capture
+    // registers of the caller, if it is a lookaround.
+    fn run(
+        &mut self,
+        program: &Program,
+        start: usize,
+        end: Option<usize>,
+        captures: &[usize],
+        visited: &mut HashSet<(usize, usize, u64)>,
+    ) -> Result<Option<usize>, String> {
+        let chars = self.chars;
+        let mut registers = vec![usize::MAX; program.registers];
+        registers[..captures.len()].copy_from_slice(captures);
+        let mut stack: Vec<Frame> = vec![];
+        let mut pc = 0;
+        let mut pos = start;
+        loop {
+            let ok = if program.memoize
+                && !visited.insert((pc, pos, fresh_loops(program, pc, pos, &registers)))
+            {
+                false
+            } else {
+                self.steps += 1;
+                if !program.memoize && self.steps > STEP_LIMIT {
+                    return Err(format!(
+                        "Backtracking limit of {} steps exceeded",
+                        STEP_LIMIT
+                    ));
+                }
+                match &program.insts[pc] {
+                    Inst::Char(c) => step(chars, &mut pos, |x| x == *c),
+                    Inst::AnyChar => step(chars, &mut pos, |_| true),
+                    Inst::CharClass(set) => step(chars, &mut pos, |x| set.contains(x)),
+                    Inst::Assertion(assertion) => assertion.holds(chars, pos),
+                    Inst::LookAround(index) => {
+                        let captures = &registers[..program.groups * 2];
+                        self.look_around_holds(&program.look_arounds[*index], pos, captures)?
+                    }
+                    Inst::Backreference(group) => {
+                        let start = registers[(group - 1) * 2];
+                        let end = registers[(group - 1) * 2 + 1];
+                        if start == usize::MAX || end == usize::MAX || start > end {
+                            false
+                        } else {
+                            let len = end - start;
+                            let matched = chars.get(pos..pos + len) == Some(&chars[start..end]);
+                            if matched {
+                                pos += len;
+                            }
+                            matched
+                        }
+                    }
+                    Inst::Split(first, second) => {
+                        stack.push(Frame::Alternative(*second, pos));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jmp(target) => {
+                        pc = *target;
+                        continue;
+                    }
+                    Inst::SavePos(register) => {
+                        stack.push(Frame::Restore(*register, registers[*register]));
+                        registers[*register] = pos;
+                        true
+                    }
+                    Inst::CheckProgress(register, exit) => {
+                        if registers[*register] == pos {
+                            pc = *exit;
+                            continue;
+                        }
+                        true
+                    }
+                    Inst::AtomicStart => {
+                        stack.push(Frame::AtomicBarrier);
+                        true
+                    }
+                    Inst::AtomicEnd => {
+                        // drop the alternatives inside the group, but keep the
+                        // restores so that backtracking past the group still works
+                        let barrier = stack
+                            .iter()
+                            .rposition(|frame| matches!(frame, Frame::AtomicBarrier))
+                            .unwrap();
+                        let inner = stack.split_off(barrier);
+                        stack.extend(
+                            inner
+                                .into_iter()
+                                .filter(|frame| matches!(frame, Frame::Restore(_, _))),
+                        );
+                        true
+                    }
+                    Inst::Match => {
+                        if end.is_none_or(|end| end == pos) {
+                            return Ok(Some(pos));
+                        }
+                        false
+                    }
+                }
+            };
+
+            if ok {
+                pc += 1;
+                continue;
+            }
+
+            // backtrack
+            loop {
+                match stack.pop() {
+                    Some(Frame::Alternative(next_pc, next_pos)) => {
+                        pc = next_pc;
+                        pos = next_pos;
+                        break;
+                    }
+                    Some(Frame::Restore(register, value)) => registers[register] = value,
+                    Some(Frame::AtomicBarrier) => {}
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+
+    fn look_around_holds(
+        &mut self,
+        look_around: &LookAroundProgram,
+        pos: usize,
+        captures: &[usize],
+    ) -> Result<bool, String> {
+        let program = &look_around.program;
+        let mut found = false;
+        if look_around.kind.is_behind() {
+            // try every start that can end exactly here
+            let max_len = look_around.max_len.unwrap_or(pos).min(pos);
+            for len in look_around.min_len..=max_len {
+                let mut visited = HashSet::new();
+                if self
+                    .run(program, pos - len, Some(pos), captures, &mut visited)?
+                    .is_some()
+                {
+                    found = true;
+                    break;
+                }
+            }
+        } else {
+            let mut visited = HashSet::new();
+            found = self
+                .run(program, pos, None, captures, &mut visited)?
+                .is_some();
+        }
+        Ok(found != look_around.kind.is_negative())
+    }
+}
+
+// returns a bit for each loop around `pc`, set if its current iteration has
+// not progressed yet, as such an iteration ends the loop at its check
+fn fresh_loops(program: &Program, pc: usize, pos: usize, registers: &[usize]) -> u64 {
+    program.loops[pc].iter().fold(0, |bits, &register| {
+        bits << 1 | (registers[register] == pos) as u64
+    })
+}
+
+fn step(chars: &[char], pos: &mut usize, predicate: impl Fn(char) -> bool) -> bool {
+    match chars.get(*pos) {
+        Some(&c) if predicate(c) => {
+            *pos += 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn find(pattern: &str, input: &str) -> Option<(usize, usize)> {
+        let program = compile(&parse(lex(pattern).unwrap()).unwrap()).unwrap();
+        find_backtrack(&program, input).unwrap()
+    }
+
+    #[test]
+    fn test_compile() {
+        let program = compile(&parse(lex("a|b").unwrap()).unwrap()).unwrap();
+        assert_eq!(
+            program.insts,
+            vec![
+                Inst::Split(1, 3),
+                Inst::Char('a'),
+                Inst::Jmp(4),
+                Inst::Char('b'),
+                Inst::Match
+            ]
+        );
+
+        let program = compile(&parse(lex("a*?").unwrap()).unwrap()).unwrap();
+        assert_eq!(
+            program.insts,
+            vec![
+                Inst::Split(5, 1),
+                Inst::SavePos(0),
+                Inst::Char('a'),
+                Inst::CheckProgress(0, 5),
+                Inst::Jmp(0),
+                Inst::Match
+            ]
+        );
+        assert_eq!(program.registers, 1);
+    }
+
+    #[test]
+    fn test_find_backtrack() {
+        assert_eq!(find("a", "a"), Some((0, 1)));
+        assert_eq!(find("ab", "aab"), Some((1, 3)));
+        assert_eq!(find("ab", "ba"), None);
+        assert_eq!(find("b|ab", "xab"), Some((1, 3)));
+        assert_eq!(find("[a-c]d", "xxbd"), Some((2, 4)));
+        assert_eq!(find("b", "あb"), Some((3, 4)));
+        assert_eq!(find("(ab)*c", "ababc"), Some((0, 5)));
+        assert_eq!(find("ab|a|abc", "abc"), Some((0, 2)));
+        assert_eq!(find("x(|a)", "xa"), Some((0, 1)));
+        assert_eq!(find("x(a|)b", "xb xab"), Some((0, 2)));
+        assert_eq!(find("", "abc"), Some((0, 0)));
+        assert_eq!(find("a()b", "ab"), Some((0, 2)));
+
+        // greedy and lazy
+        assert_eq!(find("a*", "aaa"), Some((0, 3)));
+        assert_eq!(find("a*?", "aaa"), Some((0, 0)));
+        assert_eq!(find("a+?", "baaa"), Some((1, 2)));
+        assert_eq!(find("\"(.*)\"", "\"a\", \"b\""), Some((0, 8)));
+        assert_eq!(find("\".*?\"", "\"a\", \"b\""), Some((0, 3)));
+        assert_eq!(find("a{2,3}", "aaaa"), Some((0, 3)));
+        assert_eq!(find("a{2,}?", "aaaa"), Some((0, 2)));
+
+        // empty iterations terminate
+        assert_eq!(find("(a*)*b", "aac"), None);
+        assert_eq!(find("(a?)+b", "aab"), Some((0, 3)));
+    }
+
+    #[test]
+    fn test_find_backtrack_word_boundary() {
+        assert_eq!(find("\\bid\\b", "id"), Some((0, 2)));
+        assert_eq!(find("\\bid\\b", "valid id"), Some((6, 8)));
+        assert_eq!(find("\\bid\\b", "width valid"), None);
+        assert_eq!(find("\\Bid\\B", "id width"), Some((4, 6)));
+        assert_eq!(find("\\b", ""), None);
+        assert_eq!(find("\\B", ""), Some((0, 0)));
+        assert_eq!(find("\\bé", "café é"), Some((6, 8)));
+    }
+
+    #[test]
+    fn test_find_backtrack_anchor() {
+        assert_eq!(find("^a", "ba"), None);
+        assert_eq!(find("a$", "aa"), Some((1, 2)));
+        assert_eq!(find("(?m)^b$", "a\nb\nc"), Some((2, 3)));
+        assert_eq!(find("(?mR)^b$", "a\r\nb\r\nc"), Some((3, 4)));
+        assert_eq!(find("a\\Z", "a\n"), Some((0, 1)));
+        // anchors see the whole input from inside a lookbehind
+        assert_eq!(find("(?<=^a*)b", "aab ab"), Some((2, 3)));
+        assert_eq!(find("(?<=\\Aa)b", "ab"), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_find_backtrack_look_around() {
+        assert_eq!(find("foo(?=bar)", "foobaz foobar"), Some((7, 10)));
+        assert_eq!(find("foo(?!bar)", "foobar foobaz"), Some((7, 10)));
+        assert_eq!(find("(?!test)[a-z]+", "test"), Some((1, 4)));
+        assert_eq!(find("(?<=\\$)[0-9]+", "12 $34"), Some((4, 6)));
+        assert_eq!(find("(?<!\\$)\\b[0-9]+", "$12 34"), Some((4, 6)));
+        assert_eq!(find("(?<=ab|c)d", "bd cd"), Some((4, 5)));
+        assert_eq!(find("(?<!a)", ""), Some((0, 0)));
+        assert_eq!(find("a(?!.)", "aba"), Some((2, 3)));
+        assert_eq!(find("(?=a(?<=xa))a", "a xa"), Some((3, 4)));
+        // unbounded lookbehind
+        assert_eq!(find("(?<=a+)b", "xb aab"), Some((5, 6)));
+        assert_eq!(find("(?<=x.*)b", "b xab"), Some((4, 5)));
+        assert_eq!(find("(?<!x.*)b", "xb"), None);
+    }
+
+    #[test]
+    fn test_find_backtrack_backreference() {
+        assert_eq!(find("(a|b)\\1", "abba"), Some((1, 3)));
+        assert_eq!(find("\\b([a-z]+) \\1\\b", "this is is it"), Some((5, 10)));
+        assert_eq!(find("\\b([a-z]+) \\1\\b", "this is island"), None);
+        assert_eq!(find("([a-z]+) \\1", "say the the word"), Some((4, 11)));
+        assert_eq!(
+            find("(?<q>[\"']).*?\\k<q>", "say \"it's\" ok"),
+            Some((4, 10))
+        );
+        assert_eq!(find("(a)(b)(c)\\3\\2\\1", "abccba"), Some((0, 6)));
+        assert_eq!(find("(a*)b\\1", "aabaa"), Some((0, 5)));
+        // a group that did not participate never matches
+        assert_eq!(find("(a)?b\\1", "b"), None);
+        // inside a lookaround
+        assert_eq!(find("(a)(?=\\1)", "ab aa"), Some((3, 4)));
+
+        let compile_err =
+            |pattern: &str| compile(&parse(lex(pattern).unwrap()).unwrap()).unwrap_err();
+        assert_eq!(compile_err("(a)\\2"), "Invalid backreference: \\2");
+        assert_eq!(compile_err("(a)\\k<b>"), "Unknown group name: b");
+        assert_eq!(compile_err("(?<a>x)(?<a>y)"), "Duplicate group name: a");
+    }
+
+    #[test]
+    fn test_find_backtrack_limits() {
+        // memoization keeps catastrophic patterns fast
+        let input = "a".repeat(30);
+        assert_eq!(find("(a*)*b", &input), None);
+        assert_eq!(find("(a|aa)+c", &input), None);
+
+        // without it, the search is bounded
+        let program = compile(&parse(lex("(a*)*\\1b").unwrap()).unwrap()).unwrap();
+        assert_eq!(
+            find_backtrack(&program, &input),
+            Err("Backtracking limit of 1000000 steps exceeded".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_backtrack_atomic() {
+        // the group never gives back the `a`s it matched
+        assert_eq!(find("(?>a*)a", "aaa"), None);
+        assert_eq!(find("(?>a*)b", "aab"), Some((0, 3)));
+        assert_eq!(find("a*+a", "aaa"), None);
+        assert_eq!(find("a++b", "aaab"), Some((0, 4)));
+        assert_eq!(find("a?+a", "a"), None);
+        assert_eq!(find("a{1,3}+a", "aaa"), None);
+        assert_eq!(find("a{1,3}+a", "aaaa"), Some((0, 4)));
+        // only the first alternative that matches is kept
+        assert_eq!(find("(?>ab|a)c", "abc"), Some((0, 3)));
+        assert_eq!(find("(?>a|ab)c", "abc"), None);
+        // backtracking before the group still works
+        assert_eq!(find("(a|ab)(?>c)", "abc"), Some((0, 3)));
+        assert_eq!(find("x(?>a|ab)c", "xac"), Some((0, 3)));
+        // nested
+        assert_eq!(find("(?>(?>a*)b|a)c", "aac"), Some((1, 3)));
+        assert_eq!(find("(?>(?>a*)b|a)c", "aabc"), Some((0, 4)));
+        // the group is entered at several positions from one start
+        assert_eq!(find(".?(?>a*)a", "aaa"), None);
+        assert_eq!(find("(a|b)?(?>b*)b", "abb"), None);
+    }
+
+    #[test]
+    fn test_find_backtrack_nullable_loop() {
+        assert_eq!(find("(a*)*", "aab"), Some((0, 2)));
+        assert_eq!(find("(a*)+b", "aab"), Some((0, 3)));
+        assert_eq!(find("(a*)*c", "aaaaaaaaaaaaaaaaaaaab"), None);
+        // an iteration that matches nothing ends the loop, as in Perl
+        assert_eq!(find("(|a)+", "aa"), Some((0, 0)));
+        assert_eq!(find("(|b)*", "bb"), Some((0, 0)));
+        assert_eq!(find("(|a)+b", "aab"), Some((0, 3)));
+        assert_eq!(find("(a|)+", "aa"), Some((0, 2)));
+        assert_eq!(find("(b?|.)*", "bba"), Some((0, 2)));
+        assert_eq!(find("([ab]*?)+a", "baba"), Some((0, 2)));
+        assert_eq!(find("()*", "x"), Some((0, 0)));
+        assert_eq!(find("(\\b)+a", "a"), Some((0, 1)));
+        assert_eq!(find("^*a", "ba"), Some((1, 2)));
+        assert_eq!(find("^+a", "ba"), None);
+    }
+}
@@ -0,0 +1,249 @@
+/// A set of characters, stored as sorted and non-overlapping ranges.
+///
+/// Ranges that touch are merged, so two sets with the same characters are
+/// always equal.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct CharSet {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_ranges(ranges: impl IntoIterator<Item = (char, char)>) -> Self {
+        let mut set = Self {
+            ranges: ranges
+                .into_iter()
+                .filter(|(first, last)| first <= last)
+                .collect(),
+        };
+        set.normalize();
+        set
+    }
+
+    pub fn add(&mut self, c: char) {
+        self.add_range(c, c);
+    }
+
+    pub fn add_range(&mut self, first: char, last: char) {
+        if first > last {
+            return;
+        }
+        self.ranges.push((first, last));
+        self.normalize();
+    }
+
+    pub fn union(&mut self, other: &CharSet) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.normalize();
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        let mut negated = self.negate();
+        negated.union(&other.negate());
+        negated.negate()
+    }
+
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        self.intersection(&other.negate())
+    }
+
+    /// Returns the characters in exactly one of the two sets.
+    pub fn symmetric_difference(&self, other: &CharSet) -> CharSet {
+        let mut set = self.difference(other);
+        set.union(&other.difference(self));
+        set
+    }
+
+    /// Returns the set of all the characters not in this set.
+    pub fn negate(&self) -> CharSet {
+        let mut ranges = vec![];
+        let mut first = Some('\0');
+        for &(start, end) in &self.ranges {
+            if let Some(first) = first {
+                if first < start {
+                    ranges.push((first, prev_char(start).unwrap()));
+                }
+            }
+            first = next_char(end);
+        }
+        if let Some(first) = first {
+            ranges.push((first, char::MAX));
+        }
+        CharSet { ranges }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(first, last)| {
+                if last < c {
+                    std::cmp::Ordering::Less
+                } else if first > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    #[allow(dead_code)]
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    fn normalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(first, last) in &self.ranges {
+            match merged.last_mut() {
+                Some(prev) if next_char(prev.1).is_none_or(|next| first <= next) => {
+                    prev.1 = prev.1.max(last);
+                }
+                _ => merged.push((first, last)),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+impl From<Vec<char>> for CharSet {
+    fn from(chars: Vec<char>) -> Self {
+        chars.into_iter().collect()
+    }
+}
+
+impl FromIterator<char> for CharSet {
+    fn from_iter<T: IntoIterator<Item = char>>(iter: T) -> Self {
+        Self::from_ranges(iter.into_iter().map(|c| (c, c)))
+    }
+}
+
+impl std::fmt::Display for CharSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for &(first, last) in &self.ranges {
+            if first == last {
+                write!(f, "{}", first.escape_debug())?;
+            } else {
+                write!(f, "{}-{}", first.escape_debug(), last.escape_debug())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// the next and previous characters, skipping the surrogate range
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => char::from_u32((c as u32).checked_sub(1)?),
+    }
+}
+
+/// Returns the set for a POSIX class name such as `alpha`, as used in
+/// `[[:alpha:]]`. The classes cover ASCII only, as in the C locale.
+pub fn posix_class(name: &str) -> Option<CharSet> {
+    let ranges: &[(char, char)] = match name {
+        "alpha" => &[('A', 'Z'), ('a', 'z')],
+        "digit" => &[('0', '9')],
+        "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "upper" => &[('A', 'Z')],
+        "lower" => &[('a', 'z')],
+        "space" => &[('\t', '\r'), (' ', ' ')],
+        "blank" => &[('\t', '\t'), (' ', ' ')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "print" => &[(' ', '~')],
+        "graph" => &[('!', '~')],
+        "cntrl" => &[('\0', '\x1F'), ('\x7F', '\x7F')],
+        "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+        "word" => &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        _ => return None,
+    };
+    Some(CharSet::from_ranges(ranges.iter().copied()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_set() {
+        let mut set = CharSet::new();
+        set.add_range('a', 'c');
+        set.add('e');
+        set.add('d');
+        set.add_range('x', 'z');
+        set.add_range('b', 'b');
+        assert_eq!(set.ranges(), &[('a', 'e'), ('x', 'z')]);
+        assert!(set.contains('a'));
+        assert!(set.contains('d'));
+        assert!(set.contains('y'));
+        assert!(!set.contains('f'));
+        assert!(!set.contains('A'));
+        assert_eq!(
+            set,
+            CharSet::from(vec!['y', 'a', 'b', 'c', 'd', 'e', 'x', 'z'])
+        );
+        assert_eq!(set.to_string(), "a-ex-z");
+
+        let mut other = CharSet::from_ranges([('f', 'w')]);
+        other.union(&set);
+        assert_eq!(other.ranges(), &[('a', 'z')]);
+
+        // ranges around the surrogates are adjacent
+        let set = CharSet::from_ranges([('\u{D000}', '\u{D7FF}'), ('\u{E000}', '\u{E0FF}')]);
+        assert_eq!(set.ranges(), &[('\u{D000}', '\u{E0FF}')]);
+    }
+
+    #[test]
+    fn test_negate() {
+        let set = CharSet::from_ranges([('b', 'c'), ('x', 'x')]);
+        let negated = set.negate();
+        assert_eq!(
+            negated.ranges(),
+            &[('\0', 'a'), ('d', 'w'), ('y', char::MAX)]
+        );
+        assert!(!negated.contains('b'));
+        assert!(negated.contains('😀'));
+        assert_eq!(negated.negate(), set);
+        assert_eq!(CharSet::new().negate().ranges(), &[('\0', char::MAX)]);
+        assert_eq!(CharSet::new().negate().negate(), CharSet::new());
+    }
+
+    #[test]
+    fn test_set_operation() {
+        let a = CharSet::from_ranges([('a', 'm'), ('x', 'z')]);
+        let b = CharSet::from_ranges([('k', 'y')]);
+        assert_eq!(a.intersection(&b).ranges(), &[('k', 'm'), ('x', 'y')]);
+        assert_eq!(a.difference(&b).ranges(), &[('a', 'j'), ('z', 'z')]);
+        assert_eq!(b.difference(&a).ranges(), &[('n', 'w')]);
+        assert_eq!(
+            a.symmetric_difference(&b).ranges(),
+            &[('a', 'j'), ('n', 'w'), ('z', 'z')]
+        );
+        assert_eq!(a.intersection(&CharSet::new()), CharSet::new());
+        assert_eq!(a.difference(&a), CharSet::new());
+        assert_eq!(a.symmetric_difference(&CharSet::new()), a);
+    }
+
+    #[test]
+    fn test_posix_class() {
+        let alpha = posix_class("alpha").unwrap();
+        assert!(alpha.contains('q'));
+        assert!(!alpha.contains('1'));
+        assert!(!alpha.contains('é'));
+        assert!(posix_class("space").unwrap().contains('\x0B'));
+        assert!(posix_class("punct").unwrap().contains('_'));
+        assert!(!posix_class("graph").unwrap().contains(' '));
+        assert_eq!(posix_class("Alpha"), None);
+    }
+}
@@ -1,24 +1,69 @@
+mod args;
+mod charset;
 mod lexer;
 mod nfa;
 mod parser;
+mod unicode;
 
 use std::env;
+use std::fmt::Display;
+use std::process;
 
+use args::{parse_args, Arg, Arity::*, Opt};
 use lexer::lex;
 use nfa::build_nfa;
 use parser::parse;
 
+const USAGE: &str = "Usage: dot [OPTION]... PATTERN";
+
+const OPTIONS: &[Opt] = &[
+    Opt::new(None, "help", Flag, "print this help and exit"),
+    Opt::new(Some('V'), "version", Flag, "print the version and exit"),
+];
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: {} <regex>", args[0]);
-        return;
+    let parsed = parse_args(OPTIONS, &args[1..]).unwrap_or_else(|e| usage_error(e));
+    let mut operands = vec![];
+    for arg in parsed {
+        match arg {
+            Arg::Operand(operand) => operands.push(operand),
+            Arg::Opt("help", _) => {
+                print!(
+                    "{}\nPrint the NFA of PATTERN in the DOT format of Graphviz.\n\n{}",
+                    USAGE,
+                    args::help(OPTIONS)
+                );
+                return;
+            }
+            Arg::Opt("version", _) => {
+                println!("dot {}", env!("CARGO_PKG_VERSION"));
+                return;
+            }
+            Arg::Opt(name, _) => unreachable!("unknown option --{}", name),
+        }
     }
+    let regex = match operands.as_slice() {
+        [regex] => regex,
+        [] => usage_error("no pattern given"),
+        [_, extra, ..] => usage_error(format!("extra operand '{}'", extra)),
+    };
 
-    let regex = args[1].clone();
-    let l = lex(&regex).unwrap();
-    let p = parse(l).unwrap();
-    let nfa = build_nfa(p).unwrap();
+    let nfa = lex(regex)
+        .and_then(parse)
+        .and_then(build_nfa)
+        .unwrap_or_else(|e| {
+            eprintln!("dot: {}", e);
+            process::exit(2);
+        });
 
     println!("{}", nfa.to_dot());
 }
+
+/// Reports a wrong command line, and exits with 2.
+fn usage_error(message: impl Display) -> ! {
+    eprintln!("dot: {}", message);
+    eprintln!("{}", USAGE);
+    eprintln!("Try 'dot --help' for more information.");
+    process::exit(2);
+}
@@ -1,51 +1,441 @@
+use std::iter::Peekable;
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Token {
-    Literal(char), // single character
-    Star,          // *
-    Plus,          // +
-    Question,      // ?
-    Dot,           // .
-    Hyphen,        // -
-    Pipe,          // |
-    LeftParen,     // (
-    RightParen,    // )
-    LeftBracket,   // [
-    RightBracket,  // ]
-                   // TODO: Add more tokens
+    Literal(char),              // single character
+    Star,                       // *
+    Plus,                       // +
+    Question,                   // ?
+    Dot,                        // .
+    Hyphen,                     // - in a character class
+    Pipe,                       // |
+    LeftParen,                  // (
+    AtomicGroup,                // (?>
+    LookAhead,                  // (?=
+    NegativeLookAhead,          // (?!
+    LookBehind,                 // (?<=
+    NegativeLookBehind,         // (?<!
+    NamedGroup(String),         // (?<name>
+    RightParen,                 // )
+    LeftBracket,                // [
+    RightBracket,               // ]
+    WordBoundary,               // \b
+    NotWordBoundary,            // \B
+    StartText,                  // \A, or ^ without the m flag
+    EndText,                    // \z, or $ without the m flag
+    EndTextOrNewline(bool),     // \Z, true with the R flag
+    StartLine(bool),            // ^ with the m flag, true with the R flag
+    EndLine(bool),              // $ with the m flag, true with the R flag
+    Backreference(usize),       // \1
+    NamedBackreference(String), // \k<name>
+    PosixClass(String, bool),   // [:name:] or, if negated, [:^name:]
+    Property(String, bool),     // \p{name} or, if negated, \P{name}
+    Intersection,               // && in a class
+    Difference,                 // -- in a class
+    SymmetricDifference,        // ~~ in a class
+    Repeat(usize, Option<usize>), // {n}, {n,} or {n,m}
+                                // TODO: Add more tokens
+}
+
+/// Flags that change how a pattern is tokenized.
+///
+/// Flags can also be switched inside a pattern with an inline group such as
+/// `(?x)` or `(?-x)`. An inline group applies from its position to the end
+/// of the pattern.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    /// `x`: unescaped whitespace is ignored and `#` starts a comment that
+    /// runs to the end of the line. Both are still literal inside `[...]`.
+    pub extended: bool,
+    /// `m`: `^` and `$` match at the start and the end of each line, rather
+    /// than only at the start and the end of the text.
+    pub multi_line: bool,
+    /// `R`: `\r\n` and a lone `\r` end a line as well as `\n`, for `^`, `$`
+    /// and `\Z`. No anchor matches between the `\r` and the `\n`.
+    pub crlf: bool,
+}
+
+impl Flags {
+    fn set(&mut self, flag: char, enabled: bool) -> Result<(), String> {
+        match flag {
+            'x' => self.extended = enabled,
+            'm' => self.multi_line = enabled,
+            'R' => self.crlf = enabled,
+            _ => return Err(format!("Unknown flag: {}", flag)),
+        }
+        Ok(())
+    }
 }
 
 pub fn lex(input: &str) -> Result<Vec<Token>, String> {
+    lex_with_flags(input, Flags::default())
+}
+
+pub fn lex_with_flags(input: &str, flags: Flags) -> Result<Vec<Token>, String> {
+    let mut flags = flags;
     let mut tokens = Vec::new();
+    // how many brackets are open, as classes can be nested
+    let mut class_depth: usize = 0;
     let mut it = input.chars().peekable();
     while let Some(&c) = it.peek() {
+        let in_class = class_depth > 0;
+        if flags.extended && !in_class {
+            if c.is_whitespace() {
+                it.next();
+                continue;
+            }
+            if c == '#' {
+                // skip the comment up to and including the newline
+                for c in it.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+        }
+        // in a class, only `[`, `]`, `\`, the set operators and `-` are
+        // special, e.g. [.*] matches a dot or a star
         match c {
-            '*' => tokens.push(Token::Star),
-            '+' => tokens.push(Token::Plus),
-            '?' => tokens.push(Token::Question),
-            '.' => tokens.push(Token::Dot),
-            '-' => tokens.push(Token::Hyphen),
-            '|' => tokens.push(Token::Pipe),
-            '(' => tokens.push(Token::LeftParen),
-            ')' => tokens.push(Token::RightParen),
-            '[' => tokens.push(Token::LeftBracket),
-            ']' => tokens.push(Token::RightBracket),
-            '\\' => {
-                it.next().unwrap();
-                tokens.push(Token::Literal(it.peek().unwrap().clone()));
+            '*' if !in_class => tokens.push(Token::Star),
+            '+' if !in_class => tokens.push(Token::Plus),
+            '?' if !in_class => tokens.push(Token::Question),
+            '.' if !in_class => tokens.push(Token::Dot),
+            '-' | '&' | '~' if in_class => {
+                let mut ahead = it.clone();
+                ahead.next();
+                if ahead.peek() == Some(&c) {
+                    ahead.next();
+                    tokens.push(match c {
+                        '&' => Token::Intersection,
+                        '-' => Token::Difference,
+                        _ => Token::SymmetricDifference,
+                    });
+                    it = ahead;
+                    continue;
+                }
+                tokens.push(if c == '-' {
+                    Token::Hyphen
+                } else {
+                    Token::Literal(c)
+                })
+            }
+            '|' if !in_class => tokens.push(Token::Pipe),
+            '^' if !in_class => tokens.push(if flags.multi_line {
+                Token::StartLine(flags.crlf)
+            } else {
+                Token::StartText
+            }),
+            '$' if !in_class => tokens.push(if flags.multi_line {
+                Token::EndLine(flags.crlf)
+            } else {
+                Token::EndText
+            }),
+            '(' if !in_class => {
+                it.next();
+                if it.peek() == Some(&'?') {
+                    it.next();
+                    if let Some(token) = lex_special_group(&mut it, &mut flags)? {
+                        tokens.push(token);
+                    }
+                } else {
+                    tokens.push(Token::LeftParen);
+                }
+                continue;
+            }
+            ')' if !in_class => tokens.push(Token::RightParen),
+            '{' if !in_class => {
+                let mut ahead = it.clone();
+                ahead.next();
+                if let Some(token) = lex_repeat(&mut ahead) {
+                    tokens.push(token);
+                    it = ahead;
+                    continue;
+                }
+                tokens.push(Token::Literal(c))
+            }
+            '[' => {
+                it.next();
+                if in_class {
+                    let mut ahead = it.clone();
+                    if let Some(token) = lex_posix_class(&mut ahead) {
+                        tokens.push(token);
+                        it = ahead;
+                        continue;
+                    }
+                }
+                class_depth += 1;
+                tokens.push(Token::LeftBracket);
+                // `]` right after the opening bracket is a literal, e.g. []a]
+                if it.peek() == Some(&']') {
+                    it.next();
+                    tokens.push(Token::Literal(']'));
+                }
+                continue;
+            }
+            ']' => {
+                class_depth = class_depth.saturating_sub(1);
+                tokens.push(Token::RightBracket)
             }
-            _ => {
-                if c.is_ascii_alphabetic() || c.is_ascii_digit() {
-                    tokens.push(Token::Literal(c));
-                } else if !c.is_whitespace() {
-                    return Err(format!("Invalid character: {}", c));
+            '\\' => {
+                it.next();
+                let escaped = *it.peek().ok_or_else(|| "Trailing backslash".to_string())?;
+                match escaped {
+                    // everything up to \E or the end is literal, e.g. \Qa.b\E
+                    'Q' => {
+                        it.next();
+                        while let Some(c) = it.next() {
+                            if c == '\\' && it.peek() == Some(&'E') {
+                                it.next();
+                                break;
+                            }
+                            tokens.push(Token::Literal(c));
+                        }
+                        continue;
+                    }
+                    // \E without \Q does nothing
+                    'E' => {}
+                    '1'..='9' if !in_class => {
+                        let group = lex_number(&mut it)
+                            .ok_or_else(|| "Invalid backreference".to_string())?;
+                        tokens.push(Token::Backreference(group));
+                        continue;
+                    }
+                    'k' if !in_class => {
+                        it.next();
+                        if it.next() != Some('<') {
+                            return Err("Invalid named backreference".to_string());
+                        }
+                        tokens.push(Token::NamedBackreference(lex_group_name(&mut it)?));
+                        continue;
+                    }
+                    'b' if !in_class => tokens.push(Token::WordBoundary),
+                    'B' if !in_class => tokens.push(Token::NotWordBoundary),
+                    'A' if !in_class => tokens.push(Token::StartText),
+                    'z' if !in_class => tokens.push(Token::EndText),
+                    'Z' if !in_class => tokens.push(Token::EndTextOrNewline(flags.crlf)),
+                    'p' | 'P' => {
+                        it.next();
+                        let name = lex_property_name(&mut it, escaped)?;
+                        tokens.push(Token::Property(name, escaped == 'P'));
+                        continue;
+                    }
+                    'x' | 'u' => {
+                        it.next();
+                        tokens.push(Token::Literal(lex_code_point(&mut it, escaped)?));
+                        continue;
+                    }
+                    'n' => tokens.push(Token::Literal('\n')),
+                    't' => tokens.push(Token::Literal('\t')),
+                    'r' => tokens.push(Token::Literal('\r')),
+                    'f' => tokens.push(Token::Literal('\x0C')),
+                    'v' => tokens.push(Token::Literal('\x0B')),
+                    '0' => tokens.push(Token::Literal('\0')),
+                    // letters and digits are reserved for escapes with a
+                    // meaning, e.g. \d is not a literal d
+                    _ if escaped.is_ascii_alphanumeric() => {
+                        return Err(format!("Unknown escape: \\{}", escaped))
+                    }
+                    _ => tokens.push(Token::Literal(escaped)),
                 }
             }
+            _ => tokens.push(Token::Literal(c)),
         }
         it.next();
     }
     Ok(tokens)
 }
 
+/// Lexes a counted repetition after its `{`.
+/// Returns None if the braces do not form one, in which case `{` is a literal.
+fn lex_repeat(it: &mut Peekable<impl Iterator<Item = char>>) -> Option<Token> {
+    let min = lex_number(it)?;
+    match it.next()? {
+        '}' => Some(Token::Repeat(min, Some(min))),
+        ',' => {
+            if it.peek() == Some(&'}') {
+                it.next();
+                return Some(Token::Repeat(min, None));
+            }
+            let max = lex_number(it)?;
+            (it.next()? == '}').then_some(Token::Repeat(min, Some(max)))
+        }
+        _ => None,
+    }
+}
+
+/// Lexes the name of a `\p{name}` or `\pL` escape.
+/// `it` must point just after the `p` or `P`.
+fn lex_property_name(
+    it: &mut Peekable<impl Iterator<Item = char>>,
+    escape: char,
+) -> Result<String, String> {
+    match it.next() {
+        Some('{') => {
+            let mut name = String::new();
+            loop {
+                match it.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(format!("Unclosed \\{} escape", escape)),
+                }
+            }
+            Ok(name)
+        }
+        Some(c) if c.is_ascii_alphabetic() => Ok(c.to_string()),
+        _ => Err(format!("Invalid property escape: \\{}", escape)),
+    }
+}
+
+/// Lexes the code point of a `\xHH`, `\x{H..}` or `\u{H..}` escape.
+/// `it` must point just after the `x` or `u`.
+fn lex_code_point(
+    it: &mut Peekable<impl Iterator<Item = char>>,
+    escape: char,
+) -> Result<char, String> {
+    let mut digits = String::new();
+    let braced = it.peek() == Some(&'{');
+    if braced {
+        it.next();
+        loop {
+            match it.next() {
+                Some('}') => break,
+                Some(c) => digits.push(c),
+                None => return Err(format!("Unclosed \\{} escape", escape)),
+            }
+        }
+    } else if escape == 'x' {
+        digits.extend(it.by_ref().take(2));
+    } else {
+        return Err("Invalid unicode escape: expected \\u{...}".to_string());
+    }
+    let valid = if braced {
+        (1..=8).contains(&digits.len())
+    } else {
+        digits.len() == 2
+    };
+    if !valid || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(if braced {
+            format!("Invalid hex escape: \\{}{{{}}}", escape, digits)
+        } else {
+            format!("Invalid hex escape: \\{}{}", escape, digits)
+        });
+    }
+    let value = u32::from_str_radix(&digits, 16).unwrap();
+    char::from_u32(value).ok_or_else(|| format!("Invalid code point: U+{:X}", value))
+}
+
+/// Lexes a POSIX class such as `[:alpha:]` after its `[`.
+/// Returns None if the brackets do not form one.
+fn lex_posix_class(it: &mut Peekable<impl Iterator<Item = char>>) -> Option<Token> {
+    if it.next()? != ':' {
+        return None;
+    }
+    let negated = it.peek() == Some(&'^');
+    if negated {
+        it.next();
+    }
+    let mut name = String::new();
+    loop {
+        match it.next()? {
+            ':' => break,
+            c if c.is_ascii_alphabetic() => name.push(c),
+            _ => return None,
+        }
+    }
+    (it.next()? == ']').then_some(Token::PosixClass(name, negated))
+}
+
+fn lex_number(it: &mut Peekable<impl Iterator<Item = char>>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(&c) = it.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        it.next();
+    }
+    digits.parse().ok()
+}
+
+/// Lexes a group starting with `(?`. `it` must point just after the `?`.
+/// Returns None for an inline flag group, which only updates `flags`.
+fn lex_special_group(
+    it: &mut Peekable<impl Iterator<Item = char>>,
+    flags: &mut Flags,
+) -> Result<Option<Token>, String> {
+    match it.peek() {
+        Some('>') => {
+            it.next();
+            Ok(Some(Token::AtomicGroup))
+        }
+        Some('=') => {
+            it.next();
+            Ok(Some(Token::LookAhead))
+        }
+        Some('!') => {
+            it.next();
+            Ok(Some(Token::NegativeLookAhead))
+        }
+        Some('<') => {
+            it.next();
+            match it.peek() {
+                Some('=') => {
+                    it.next();
+                    Ok(Some(Token::LookBehind))
+                }
+                Some('!') => {
+                    it.next();
+                    Ok(Some(Token::NegativeLookBehind))
+                }
+                _ => Ok(Some(Token::NamedGroup(lex_group_name(it)?))),
+            }
+        }
+        _ => {
+            lex_flag_group(it, flags)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Lexes a group name up to and including the closing `>`.
+fn lex_group_name(it: &mut Peekable<impl Iterator<Item = char>>) -> Result<String, String> {
+    let mut name = String::new();
+    loop {
+        match it.next() {
+            Some('>') => break,
+            Some(c) => name.push(c),
+            None => return Err("Unclosed group name".to_string()),
+        }
+    }
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && name.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    if !valid {
+        return Err(format!("Invalid group name: {}", name));
+    }
+    Ok(name)
+}
+
+/// Lexes an inline flag group such as `(?x)` or `(?x-x)`.
+fn lex_flag_group(
+    it: &mut Peekable<impl Iterator<Item = char>>,
+    flags: &mut Flags,
+) -> Result<(), String> {
+    let mut enabled = true;
+    let mut new_flags = *flags;
+    loop {
+        match it.next() {
+            Some(')') => break,
+            Some('-') if enabled => enabled = false,
+            Some(c) if c.is_ascii_alphabetic() => new_flags.set(c, enabled)?,
+            Some(c) => return Err(format!("Invalid character in flag group: {}", c)),
+            None => return Err("Unclosed flag group".to_string()),
+        }
+    }
+    *flags = new_flags;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,6 +448,15 @@ mod tests {
             lex("aa").unwrap(),
             vec![Token::Literal('a'), Token::Literal('a')]
         );
+        // - is only special in a character class
+        assert_eq!(
+            lex("-a-").unwrap(),
+            vec![
+                Token::Literal('-'),
+                Token::Literal('a'),
+                Token::Literal('-')
+            ]
+        );
         assert_eq!(lex("a*").unwrap(), vec![Token::Literal('a'), Token::Star]);
         assert_eq!(lex("a+").unwrap(), vec![Token::Literal('a'), Token::Plus]);
         assert_eq!(
@@ -87,13 +486,480 @@ mod tests {
         );
         assert_eq!(
             lex(" a-z ").unwrap(),
-            vec![Token::Literal('a'), Token::Hyphen, Token::Literal('z')]
+            vec![
+                Token::Literal(' '),
+                Token::Literal('a'),
+                Token::Literal('-'),
+                Token::Literal('z'),
+                Token::Literal(' ')
+            ]
         );
         assert_eq!(
             lex("a|b").unwrap(),
             vec![Token::Literal('a'), Token::Pipe, Token::Literal('b')]
         );
-        assert_eq!(lex("\\a").unwrap(), vec![Token::Literal('a')]);
+        assert_eq!(lex("\\a"), Err("Unknown escape: \\a".to_string()));
         assert_eq!(lex("\\-").unwrap(), vec![Token::Literal('-')]);
+        assert_eq!(
+            lex("\\ba\\B").unwrap(),
+            vec![
+                Token::WordBoundary,
+                Token::Literal('a'),
+                Token::NotWordBoundary
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_escape() {
+        assert_eq!(
+            lex("\\n\\t\\r\\f\\v\\0").unwrap(),
+            vec![
+                Token::Literal('\n'),
+                Token::Literal('\t'),
+                Token::Literal('\r'),
+                Token::Literal('\x0C'),
+                Token::Literal('\x0B'),
+                Token::Literal('\0')
+            ]
+        );
+        assert_eq!(
+            lex("\\x41\\x{3bb}\\u{1F600}").unwrap(),
+            vec![
+                Token::Literal('A'),
+                Token::Literal('λ'),
+                Token::Literal('😀')
+            ]
+        );
+        assert_eq!(
+            lex("[\\t\\x20]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('\t'),
+                Token::Literal(' '),
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(lex("\\x4g"), Err("Invalid hex escape: \\x4g".to_string()));
+        assert_eq!(lex("\\x4"), Err("Invalid hex escape: \\x4".to_string()));
+        assert_eq!(
+            lex("\\x{zz}"),
+            Err("Invalid hex escape: \\x{zz}".to_string())
+        );
+        assert_eq!(lex("\\u{}"), Err("Invalid hex escape: \\u{}".to_string()));
+        assert_eq!(lex("\\u{41"), Err("Unclosed \\u escape".to_string()));
+        assert_eq!(
+            lex("\\u41"),
+            Err("Invalid unicode escape: expected \\u{...}".to_string())
+        );
+        assert_eq!(
+            lex("\\x{110000}"),
+            Err("Invalid code point: U+110000".to_string())
+        );
+        assert_eq!(
+            lex("\\u{D800}"),
+            Err("Invalid code point: U+D800".to_string())
+        );
+        assert_eq!(lex("a\\"), Err("Trailing backslash".to_string()));
+
+        // other characters are literal, but letters and digits are reserved
+        assert_eq!(
+            lex("\\.\\ \\é").unwrap(),
+            vec![
+                Token::Literal('.'),
+                Token::Literal(' '),
+                Token::Literal('é')
+            ]
+        );
+        assert_eq!(lex("\\d"), Err("Unknown escape: \\d".to_string()));
+        assert_eq!(lex("\\w+"), Err("Unknown escape: \\w".to_string()));
+        assert_eq!(lex("[\\s]"), Err("Unknown escape: \\s".to_string()));
+    }
+
+    #[test]
+    fn test_lexer_posix_class() {
+        assert_eq!(
+            lex("[[:alpha:][:^digit:]_]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::PosixClass("alpha".to_string(), false),
+                Token::PosixClass("digit".to_string(), true),
+                Token::Literal('_'),
+                Token::RightBracket
+            ]
+        );
+        // only inside brackets
+        assert_eq!(
+            lex("[:a:]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal(':'),
+                Token::Literal('a'),
+                Token::Literal(':'),
+                Token::RightBracket
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_property() {
+        assert_eq!(
+            lex("\\p{Greek}\\PL[\\p{Lu}]").unwrap(),
+            vec![
+                Token::Property("Greek".to_string(), false),
+                Token::Property("L".to_string(), true),
+                Token::LeftBracket,
+                Token::Property("Lu".to_string(), false),
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(lex("\\p{L"), Err("Unclosed \\p escape".to_string()));
+        assert_eq!(lex("\\P1"), Err("Invalid property escape: \\P".to_string()));
+    }
+
+    #[test]
+    fn test_lexer_class_literal() {
+        assert_eq!(
+            lex("[.*(?)|][+]+").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('.'),
+                Token::Literal('*'),
+                Token::Literal('('),
+                Token::Literal('?'),
+                Token::Literal(')'),
+                Token::Literal('|'),
+                Token::RightBracket,
+                Token::LeftBracket,
+                Token::Literal('+'),
+                Token::RightBracket,
+                Token::Plus
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_class_operator() {
+        assert_eq!(
+            lex("[a-z--[aeiou]]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('a'),
+                Token::Hyphen,
+                Token::Literal('z'),
+                Token::Difference,
+                Token::LeftBracket,
+                Token::Literal('a'),
+                Token::Literal('e'),
+                Token::Literal('i'),
+                Token::Literal('o'),
+                Token::Literal('u'),
+                Token::RightBracket,
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(
+            lex("[a&&b~~c&~]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('a'),
+                Token::Intersection,
+                Token::Literal('b'),
+                Token::SymmetricDifference,
+                Token::Literal('c'),
+                Token::Literal('&'),
+                Token::Literal('~'),
+                Token::RightBracket
+            ]
+        );
+        // only inside brackets
+        assert_eq!(
+            lex("&&~~").unwrap(),
+            vec![
+                Token::Literal('&'),
+                Token::Literal('&'),
+                Token::Literal('~'),
+                Token::Literal('~')
+            ]
+        );
+        // the class ends at the outer bracket
+        assert_eq!(
+            lex("[[a]]{2}").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::LeftBracket,
+                Token::Literal('a'),
+                Token::RightBracket,
+                Token::RightBracket,
+                Token::Repeat(2, Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_anchor() {
+        assert_eq!(
+            lex("^a$").unwrap(),
+            vec![Token::StartText, Token::Literal('a'), Token::EndText]
+        );
+        assert_eq!(
+            lex("\\Aa\\z\\Z").unwrap(),
+            vec![
+                Token::StartText,
+                Token::Literal('a'),
+                Token::EndText,
+                Token::EndTextOrNewline(false)
+            ]
+        );
+        assert_eq!(
+            lex("(?m)^$(?R)^$\\Z(?-m)^").unwrap(),
+            vec![
+                Token::StartLine(false),
+                Token::EndLine(false),
+                Token::StartLine(true),
+                Token::EndLine(true),
+                Token::EndTextOrNewline(true),
+                Token::StartText
+            ]
+        );
+        // literal inside brackets
+        assert_eq!(
+            lex("[$^]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('$'),
+                Token::Literal('^'),
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(lex("\\$").unwrap(), vec![Token::Literal('$')]);
+    }
+
+    #[test]
+    fn test_lexer_quote() {
+        assert_eq!(
+            lex("\\Qa.*\\E+").unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal('.'),
+                Token::Literal('*'),
+                Token::Plus
+            ]
+        );
+        // up to the end without \E
+        assert_eq!(
+            lex("\\Q(\\n").unwrap(),
+            vec![
+                Token::Literal('('),
+                Token::Literal('\\'),
+                Token::Literal('n')
+            ]
+        );
+        assert_eq!(
+            lex("[\\Q]-\\E]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal(']'),
+                Token::Literal('-'),
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(
+            lex_with_flags("(?x)\\Qa #b\\E", Flags::default()).unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal(' '),
+                Token::Literal('#'),
+                Token::Literal('b')
+            ]
+        );
+        assert_eq!(lex("\\Q\\E").unwrap(), vec![]);
+        assert_eq!(lex("a\\E").unwrap(), vec![Token::Literal('a')]);
+    }
+
+    #[test]
+    fn test_lexer_atomic() {
+        assert_eq!(
+            lex("(?>a)").unwrap(),
+            vec![Token::AtomicGroup, Token::Literal('a'), Token::RightParen]
+        );
+        assert_eq!(
+            lex("a*+").unwrap(),
+            vec![Token::Literal('a'), Token::Star, Token::Plus]
+        );
+    }
+
+    #[test]
+    fn test_lexer_look_around() {
+        assert_eq!(
+            lex("(?=a)(?!b)").unwrap(),
+            vec![
+                Token::LookAhead,
+                Token::Literal('a'),
+                Token::RightParen,
+                Token::NegativeLookAhead,
+                Token::Literal('b'),
+                Token::RightParen
+            ]
+        );
+        assert_eq!(
+            lex("(?<=\\$)(?<!c)").unwrap(),
+            vec![
+                Token::LookBehind,
+                Token::Literal('$'),
+                Token::RightParen,
+                Token::NegativeLookBehind,
+                Token::Literal('c'),
+                Token::RightParen
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_backreference() {
+        assert_eq!(
+            lex("(a)\\1").unwrap(),
+            vec![
+                Token::LeftParen,
+                Token::Literal('a'),
+                Token::RightParen,
+                Token::Backreference(1)
+            ]
+        );
+        assert_eq!(
+            lex("\\12a").unwrap(),
+            vec![Token::Backreference(12), Token::Literal('a')]
+        );
+        assert_eq!(
+            lex("(?<q_1>a)\\k<q_1>").unwrap(),
+            vec![
+                Token::NamedGroup("q_1".to_string()),
+                Token::Literal('a'),
+                Token::RightParen,
+                Token::NamedBackreference("q_1".to_string())
+            ]
+        );
+        // there are no backreferences inside brackets
+        assert_eq!(lex("[\\1]"), Err("Unknown escape: \\1".to_string()));
+        assert_eq!(lex("(?<1a>x)"), Err("Invalid group name: 1a".to_string()));
+        assert_eq!(lex("(?<>x)"), Err("Invalid group name: ".to_string()));
+        assert_eq!(lex("(?<ab"), Err("Unclosed group name".to_string()));
+        assert_eq!(lex("\\kx"), Err("Invalid named backreference".to_string()));
+    }
+
+    #[test]
+    fn test_lexer_repeat() {
+        assert_eq!(
+            lex("a{2}").unwrap(),
+            vec![Token::Literal('a'), Token::Repeat(2, Some(2))]
+        );
+        assert_eq!(
+            lex("a{2,}").unwrap(),
+            vec![Token::Literal('a'), Token::Repeat(2, None)]
+        );
+        assert_eq!(
+            lex("a{2,3}?").unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Repeat(2, Some(3)),
+                Token::Question
+            ]
+        );
+        // not a repetition, so the braces are literals
+        assert_eq!(
+            lex("a{,3}").unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal('{'),
+                Token::Literal(','),
+                Token::Literal('3'),
+                Token::Literal('}')
+            ]
+        );
+        assert_eq!(
+            lex("{x}").unwrap(),
+            vec![
+                Token::Literal('{'),
+                Token::Literal('x'),
+                Token::Literal('}')
+            ]
+        );
+        assert_eq!(
+            lex("[{1}]").unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal('{'),
+                Token::Literal('1'),
+                Token::Literal('}'),
+                Token::RightBracket
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_extended() {
+        let extended = Flags {
+            extended: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            lex_with_flags(" a-z ", extended).unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal('-'),
+                Token::Literal('z')
+            ]
+        );
+        assert_eq!(
+            lex_with_flags("a # comment\n b # another\n", extended).unwrap(),
+            vec![Token::Literal('a'), Token::Literal('b')]
+        );
+        assert_eq!(
+            lex_with_flags("a\\ b\\#", extended).unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal(' '),
+                Token::Literal('b'),
+                Token::Literal('#')
+            ]
+        );
+        // whitespace and `#` are literal inside brackets
+        assert_eq!(
+            lex_with_flags("[ #]", extended).unwrap(),
+            vec![
+                Token::LeftBracket,
+                Token::Literal(' '),
+                Token::Literal('#'),
+                Token::RightBracket
+            ]
+        );
+        assert_eq!(
+            lex("a#b").unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal('#'),
+                Token::Literal('b')
+            ]
+        );
+
+        // inline flag groups
+        assert_eq!(
+            lex("(?x) a b").unwrap(),
+            vec![Token::Literal('a'), Token::Literal('b')]
+        );
+        assert_eq!(
+            lex("(?x) a (?-x) b").unwrap(),
+            vec![
+                Token::Literal('a'),
+                Token::Literal(' '),
+                Token::Literal('b')
+            ]
+        );
+        assert_eq!(
+            lex_with_flags("a (?-x)b", extended).unwrap(),
+            vec![Token::Literal('a'), Token::Literal('b')]
+        );
+        assert_eq!(lex("(?q)a"), Err("Unknown flag: q".to_string()));
+        assert_eq!(lex("(?x"), Err("Unclosed flag group".to_string()));
     }
 }
@@ -1,43 +1,806 @@
+mod args;
+mod backtrack;
+mod charset;
 mod lexer;
 mod nfa;
 mod parser;
+mod regex;
+mod unicode;
 
-use std::io::BufRead;
-use std::{env, io};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::process;
 
+use args::{parse_args, Arg, Arity::*, Opt};
 use lexer::lex;
-use nfa::{build_nfa, match_nfa};
 use parser::parse;
+use regex::{Engine, Regex};
+
+/// How files with binary data are searched, as with `--binary-files`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum BinaryFiles {
+    // report only that the file matches
+    #[default]
+    Binary,
+    Text,
+    // as if the file had no matching lines
+    WithoutMatch,
+}
+
+#[derive(Default)]
+struct Options {
+    recursive: bool,
+    with_filename: bool,
+    line_number: bool,
+    count: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    invert_match: bool,
+    only_matching: bool,
+    color: bool,
+    before_context: usize,
+    after_context: usize,
+    quiet: bool,
+    binary_files: BinaryFiles,
+    max_count: Option<usize>,
+    byte_offset: bool,
+    // true if -A, -B or -C is given, even with 0, which still separates
+    // the groups of lines
+    context: bool,
+}
+
+/// What has happened so far, across all the files searched.
+struct State {
+    // all the output, buffered unless it is a terminal
+    out: Box<dyn Write>,
+    // true once a group of lines has been printed with context
+    printed_group: bool,
+    // true once a line is selected, or a file listed with -L
+    matched: bool,
+    // true if a file could not be searched
+    error: bool,
+}
+
+impl State {
+    fn new() -> Self {
+        let stdout = io::stdout();
+        let out: Box<dyn Write> = if stdout.is_terminal() {
+            Box::new(stdout.lock())
+        } else {
+            Box::new(BufWriter::new(stdout.lock()))
+        };
+        Self {
+            out,
+            printed_group: false,
+            matched: false,
+            error: false,
+        }
+    }
+
+    /// Reports an error that does not stop the search, which still makes it
+    /// exit with 2.
+    fn error(&mut self, message: impl Display) {
+        eprintln!("l2r2grep: {}", message);
+        self.error = true;
+    }
+}
+
+// SGR codes of the colored parts, as in GNU grep
+const MATCH_COLOR: &str = "01;31";
+const FILENAME_COLOR: &str = "35";
+const LINE_NUMBER_COLOR: &str = "32";
+const BYTE_OFFSET_COLOR: &str = "32";
+const SEPARATOR_COLOR: &str = "36";
+
+const USAGE: &str = "Usage: l2r2grep [OPTION]... PATTERN [FILE]...";
+
+const OPTIONS: &[Opt] = &[
+    Opt::new(
+        Some('e'),
+        "regexp",
+        Value("PATTERN"),
+        "use PATTERN for matching",
+    ),
+    Opt::new(
+        Some('f'),
+        "file",
+        Value("FILE"),
+        "take the patterns from FILE, one per line",
+    ),
+    Opt::new(Some('x'), "line-regexp", Flag, "match only whole lines"),
+    Opt::new(Some('w'), "word-regexp", Flag, "match only whole words"),
+    Opt::new(
+        Some('v'),
+        "invert-match",
+        Flag,
+        "select the lines that do not match",
+    ),
+    Opt::new(
+        Some('r'),
+        "recursive",
+        Flag,
+        "search directories recursively",
+    ),
+    Opt::new(
+        Some('n'),
+        "line-number",
+        Flag,
+        "print the line number of each line",
+    ),
+    Opt::new(
+        Some('H'),
+        "with-filename",
+        Flag,
+        "print the file name of each line",
+    ),
+    Opt::new(Some('h'), "no-filename", Flag, "never print file names"),
+    Opt::new(
+        Some('o'),
+        "only-matching",
+        Flag,
+        "print only the matched parts of lines",
+    ),
+    Opt::new(
+        Some('c'),
+        "count",
+        Flag,
+        "print only the number of selected lines of each file",
+    ),
+    Opt::new(
+        Some('l'),
+        "files-with-matches",
+        Flag,
+        "print only the names of files with selected lines",
+    ),
+    Opt::new(
+        Some('L'),
+        "files-without-match",
+        Flag,
+        "print only the names of files without selected lines",
+    ),
+    Opt::new(
+        Some('q'),
+        "quiet",
+        Flag,
+        "print nothing, and exit with 0 at the first selected line",
+    ),
+    Opt::new(
+        Some('m'),
+        "max-count",
+        Value("NUM"),
+        "stop reading a file after NUM selected lines",
+    ),
+    Opt::new(
+        Some('b'),
+        "byte-offset",
+        Flag,
+        "print the byte offset of each line, or of each part with -o",
+    ),
+    Opt::new(
+        Some('a'),
+        "text",
+        Flag,
+        "search binary files as if they were text",
+    ),
+    Opt::new(Some('I'), "no-binary", Flag, "skip binary files"),
+    Opt::new(
+        None,
+        "binary-files",
+        Value("TYPE"),
+        "search binary files as TYPE: binary (the default), text or without-match",
+    ),
+    Opt::new(
+        Some('A'),
+        "after-context",
+        Value("NUM"),
+        "print NUM lines after each selected line",
+    ),
+    Opt::new(
+        Some('B'),
+        "before-context",
+        Value("NUM"),
+        "print NUM lines before each selected line",
+    ),
+    Opt::new(
+        Some('C'),
+        "context",
+        Value("NUM"),
+        "print NUM lines around each selected line",
+    ),
+    Opt::new(
+        None,
+        "color",
+        OptionalValue("WHEN"),
+        "highlight matches; WHEN is auto (the default), always or never",
+    ),
+    Opt::new(None, "colour", OptionalValue("WHEN"), ""),
+    Opt::new(None, "help", Flag, "print this help and exit"),
+    Opt::new(Some('V'), "version", Flag, "print the version and exit"),
+];
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: {} <regex>", args[0]);
-        return;
-    }
-
-    let regex = args[1].clone();
-    let l = lex(&regex).unwrap();
-    let p = parse(l).unwrap();
-    let nfa = build_nfa(p).unwrap();
-
-    let stdin = io::stdin();
-    let handle = stdin.lock();
-    let lines = handle.lines();
-
-    for line in lines {
-        match line {
-            Ok(input) => {
-                let result = match_nfa(&nfa, &input);
-                let matched = result.unwrap();
-                if matched {
-                    println!("{}", input);
-                    continue;
+    let parsed = parse_args(OPTIONS, &args[1..]).unwrap_or_else(|e| usage_error(e));
+    let mut options = Options::default();
+    // -H and -h override the default, which depends on the files
+    let mut with_filename = None;
+    let mut line_regexp = false;
+    let mut word_regexp = false;
+    let mut color = "auto".to_string();
+    // the patterns of -e and -f, if any
+    let mut patterns: Option<Vec<String>> = None;
+    let mut operands = vec![];
+    for arg in parsed {
+        let (name, value) = match arg {
+            Arg::Operand(operand) => {
+                operands.push(operand);
+                continue;
+            }
+            Arg::Opt(name, value) => (name, value.unwrap_or_default()),
+        };
+        match name {
+            // as with -f, each line is a pattern, but an empty value is
+            // still the empty pattern
+            "regexp" if value.is_empty() => patterns.get_or_insert_with(Vec::new).push(value),
+            "regexp" => patterns
+                .get_or_insert_with(Vec::new)
+                .extend(value.lines().map(str::to_string)),
+            "file" => patterns
+                .get_or_insert_with(Vec::new)
+                .extend(read_patterns(&value)),
+            "line-regexp" => line_regexp = true,
+            "word-regexp" => word_regexp = true,
+            "invert-match" => options.invert_match = true,
+            "recursive" => options.recursive = true,
+            "line-number" => options.line_number = true,
+            "with-filename" => with_filename = Some(true),
+            "no-filename" => with_filename = Some(false),
+            "only-matching" => options.only_matching = true,
+            "count" => options.count = true,
+            "files-with-matches" => options.files_with_matches = true,
+            "files-without-match" => options.files_without_match = true,
+            "quiet" => options.quiet = true,
+            "max-count" => {
+                let Ok(max) = value.parse::<usize>() else {
+                    fail(format!("invalid max count '{}'", value));
+                };
+                options.max_count = Some(max);
+            }
+            "byte-offset" => options.byte_offset = true,
+            "text" => options.binary_files = BinaryFiles::Text,
+            "no-binary" => options.binary_files = BinaryFiles::WithoutMatch,
+            "binary-files" => {
+                options.binary_files = match value.as_str() {
+                    "binary" => BinaryFiles::Binary,
+                    "text" => BinaryFiles::Text,
+                    "without-match" => BinaryFiles::WithoutMatch,
+                    _ => fail(format!("invalid argument '{}' for '--binary-files'", value)),
+                }
+            }
+            "after-context" | "before-context" | "context" => {
+                let Ok(lines) = value.parse::<usize>() else {
+                    fail(format!("{}: invalid context length argument", value));
+                };
+                if name != "after-context" {
+                    options.before_context = lines;
+                }
+                if name != "before-context" {
+                    options.after_context = lines;
+                }
+                options.context = true;
+            }
+            // --color alone is --color=auto
+            "color" | "colour" => {
+                color = if value.is_empty() {
+                    "auto".to_string()
+                } else {
+                    value
+                }
+            }
+            "help" => {
+                check_output(write!(io::stdout(), "{}\nSearch for PATTERN in each FILE, or in the standard input if there is none.\n\n{}", USAGE, args::help(OPTIONS)));
+                process::exit(0);
+            }
+            "version" => {
+                check_output(writeln!(
+                    io::stdout(),
+                    "l2r2grep {}",
+                    env!("CARGO_PKG_VERSION")
+                ));
+                process::exit(0);
+            }
+            _ => unreachable!("unknown option --{}", name),
+        }
+    }
+    // without -e and -f, the first operand is the pattern
+    let patterns = match patterns {
+        Some(patterns) => patterns,
+        None if !operands.is_empty() => vec![operands.remove(0)],
+        None => usage_error("no pattern given"),
+    };
+    // auto colors only a terminal, and not if NO_COLOR is set
+    options.color = match color.as_str() {
+        "always" => true,
+        "never" => false,
+        "auto" => {
+            io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        }
+        _ => fail(format!("invalid argument '{}' for '--color'", color)),
+    };
+
+    // all the patterns are searched for at once, as one alternation
+    let nodes = patterns
+        .iter()
+        .map(|pattern| parse(lex(pattern)?))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| fail(e));
+    let mut node = regex::combine(nodes);
+    // -x wins over -w, as in grep
+    if line_regexp {
+        node = regex::whole_line(node);
+    } else if word_regexp {
+        node = regex::whole_word(node);
+    }
+    let engine = Engine::for_node(&node);
+    let regex = Regex::from_node(node, engine).unwrap_or_else(|e| fail(e));
+
+    // without files, grep -r searches the working directory and grep the
+    // standard input
+    let mut paths = operands;
+    if paths.is_empty() {
+        paths.push(if options.recursive { "." } else { "-" }.to_string());
+    }
+    options.with_filename = with_filename.unwrap_or_else(|| {
+        paths.len() > 1 || (options.recursive && paths.iter().any(|p| Path::new(p).is_dir()))
+    });
+
+    let mut state = State::new();
+    let searched = paths
+        .iter()
+        .try_for_each(|path| grep_path(&regex, &options, &mut state, Path::new(path)))
+        .and_then(|_| state.out.flush());
+    check_output(searched);
+
+    // 0 if a line is selected, 1 if not and 2 on an error, which -q ignores
+    // once a line is selected
+    if state.error && !(options.quiet && state.matched) {
+        process::exit(2);
+    }
+    process::exit(if state.matched { 0 } else { 1 });
+}
+
+/// Reports a wrong command line, and exits with 2.
+fn usage_error(message: impl Display) -> ! {
+    eprintln!("l2r2grep: {}", message);
+    eprintln!("{}", USAGE);
+    eprintln!("Try 'l2r2grep --help' for more information.");
+    process::exit(2);
+}
+
+/// Reports an error that stops l2r2grep, and exits with 2.
+fn fail(message: impl Display) -> ! {
+    eprintln!("l2r2grep: {}", message);
+    process::exit(2);
+}
+
+/// Handles the result of writing the output: a reader that has gone, e.g.
+/// head, only means that there is nothing left to do, but any other error
+/// exits with 2.
+fn check_output(written: io::Result<()>) {
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => fail(format!("write error: {}", io_error_message(&e))),
+        Ok(()) => {}
+    }
+}
+
+/// Returns the message of an I/O error without the error code that Rust
+/// appends, e.g. "No such file or directory".
+fn io_error_message(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.find(" (os error ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+/// Reads the patterns of `-f`, one per line. `-` is the standard input.
+fn read_patterns(path: &str) -> Vec<String> {
+    let text = if path == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    };
+    match text {
+        Ok(text) => text.lines().map(str::to_string).collect(),
+        Err(e) => fail(format!("{}: {}", path, io_error_message(&e))),
+    }
+}
+
+/// Searches a file (the standard input for `-`), or the files under a
+/// directory with `-r`. Files that cannot be read are reported and skipped;
+/// only an error writing the output is returned.
+fn grep_path(regex: &Regex, options: &Options, state: &mut State, path: &Path) -> io::Result<()> {
+    if path == Path::new("-") {
+        let stdin = io::stdin();
+        return grep(regex, options, state, stdin.lock(), "(standard input)");
+    }
+    if path.is_dir() {
+        if !options.recursive {
+            state.error(format!("{}: Is a directory", path.display()));
+            return Ok(());
+        }
+        let mut entries =
+            match fs::read_dir(path).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    state.error(format!("{}: {}", path.display(), io_error_message(&e)));
+                    return Ok(());
+                }
+            };
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            // like grep -r, symbolic links are only followed on the command line
+            if entry
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_symlink())
+            {
+                continue;
+            }
+            grep_path(regex, options, state, &entry.path())?;
+        }
+        return Ok(());
+    }
+
+    match File::open(path) {
+        Ok(file) => grep(
+            regex,
+            options,
+            state,
+            BufReader::new(file),
+            &path.display().to_string(),
+        ),
+        Err(e) => {
+            state.error(format!("{}: {}", path.display(), io_error_message(&e)));
+            Ok(())
+        }
+    }
+}
+
+/// Prints the lines of `reader` that match `regex` (or do not, with `-v`),
+/// or a summary of them with `-c`, `-l` or `-L`. Returns an error writing
+/// the output; an error reading `reader` is reported and ends the search.
+fn grep(
+    regex: &Regex,
+    options: &Options,
+    state: &mut State,
+    mut reader: impl BufRead,
+    name: &str,
+) -> io::Result<()> {
+    let mut buf = vec![];
+    let mut line_number = 0;
+    let mut offset = 0;
+    let mut count = 0;
+    // context is only printed around whole lines
+    let context = options.context
+        && !(options.only_matching
+            || options.count
+            || options.files_with_matches
+            || options.files_without_match);
+    // the last lines that were not printed, as read, for -B
+    let mut before: VecDeque<(Position, Vec<u8>)> = VecDeque::new();
+    // the number of lines still to print after a match, for -A
+    let mut after = 0;
+    let mut last_printed = None;
+    // a file is binary if its first block has a NUL
+    let binary = options.binary_files != BinaryFiles::Text
+        && reader.fill_buf().is_ok_and(|block| block.contains(&0));
+    if binary && options.binary_files == BinaryFiles::WithoutMatch {
+        print_summary(options, state, name, 0)?;
+        return Ok(());
+    }
+    loop {
+        // after -m lines, only the context after the last one is read
+        let done = options.max_count.is_some_and(|max| count >= max);
+        if done && after == 0 {
+            break;
+        }
+        buf.clear();
+        let position = match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(read) => {
+                line_number += 1;
+                offset += read;
+                Position {
+                    line_number,
+                    byte_offset: offset - read,
                 }
             }
             Err(e) => {
-                println!("{}", e);
+                state.error(format!("{}: {}", name, io_error_message(&e)));
+                return Ok(());
+            }
+        };
+        // the line is matched without its terminator, and printed as read
+        let mut end = buf.len();
+        if buf[..end].ends_with(b"\n") {
+            end -= 1;
+            if buf[..end].ends_with(b"\r") {
+                end -= 1;
             }
         }
+        let (content, terminator) = buf.split_at(end);
+        let line = DecodedLine::new(content);
+        // the spans are only needed to print or color the matched parts
+        let matches = if options.only_matching || (options.color && !options.invert_match) {
+            regex.find_all(&line.text)
+        } else {
+            regex.find(&line.text).map(Vec::from_iter)
+        };
+        // from here on, the spans are in the line as read
+        let matches = matches.map(|matches| {
+            matches
+                .into_iter()
+                .map(|found| regex::Match {
+                    start: line.original_offset(found.start),
+                    end: line.original_offset(found.end),
+                })
+                .collect::<Vec<_>>()
+        });
+        let matches = match matches {
+            Ok(matches) if !done && matches.is_empty() == options.invert_match => matches,
+            Ok(_) if context => {
+                if after > 0 {
+                    after -= 1;
+                    print_separator(options, state, last_printed, line_number)?;
+                    print_line(
+                        &mut state.out,
+                        &prefix(options, name, Some(position), '-'),
+                        &buf,
+                    )?;
+                    last_printed = Some(line_number);
+                } else if options.before_context > 0 {
+                    if before.len() == options.before_context {
+                        before.pop_front();
+                    }
+                    before.push_back((position, buf.clone()));
+                }
+                continue;
+            }
+            Ok(_) => continue,
+            Err(e) => {
+                state.error(format!("{}:{}: {}", name, line_number, e));
+                continue;
+            }
+        };
+        // the first selected line decides the exit status of -q
+        if options.quiet {
+            process::exit(0);
+        }
+        count += 1;
+        // the first match decides -l and -L
+        if options.files_with_matches || options.files_without_match {
+            break;
+        }
+        if options.count {
+            continue;
+        }
+        // the line is selected even if printing it fails
+        state.matched = true;
+        if binary {
+            writeln!(state.out, "Binary file {} matches", name)?;
+            break;
+        }
+        // print the lines before, and start counting the lines after
+        if context {
+            for (before_position, before_line) in before.drain(..) {
+                let before_number = before_position.line_number;
+                print_separator(options, state, last_printed, before_number)?;
+                print_line(
+                    &mut state.out,
+                    &prefix(options, name, Some(before_position), '-'),
+                    &before_line,
+                )?;
+                last_printed = Some(before_number);
+            }
+            print_separator(options, state, last_printed, line_number)?;
+            last_printed = Some(line_number);
+            after = options.after_context;
+        }
+        if options.only_matching {
+            for found in matches.iter().filter(|found| found.start < found.end) {
+                // -b gives the offset of the part
+                let position = Position {
+                    byte_offset: position.byte_offset + found.start,
+                    ..position
+                };
+                print_line(
+                    &mut state.out,
+                    &prefix(options, name, Some(position), ':'),
+                    &paint_bytes(options, MATCH_COLOR, &content[found.start..found.end]),
+                )?;
+            }
+        } else {
+            let mut highlighted = highlight(options, content, &matches);
+            highlighted.extend_from_slice(terminator);
+            print_line(
+                &mut state.out,
+                &prefix(options, name, Some(position), ':'),
+                &highlighted,
+            )?;
+        }
+    }
+
+    print_summary(options, state, name, count)
+}
+
+/// A line decoded as UTF-8 to be matched, with each invalid sequence
+/// replaced by U+FFFD as in `String::from_utf8_lossy`.
+struct DecodedLine {
+    text: String,
+    // the offset in the line as read of each byte of `text` and of its
+    // end; empty if the line is valid UTF-8, so that the offsets are the same
+    offsets: Vec<usize>,
+}
+
+impl DecodedLine {
+    fn new(bytes: &[u8]) -> Self {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return Self {
+                text: text.to_string(),
+                offsets: vec![],
+            };
+        }
+        let mut text = String::with_capacity(bytes.len());
+        let mut offsets = Vec::with_capacity(bytes.len() + 1);
+        let mut offset = 0;
+        for chunk in bytes.utf8_chunks() {
+            text.push_str(chunk.valid());
+            offsets.extend(offset..offset + chunk.valid().len());
+            offset += chunk.valid().len();
+            if !chunk.invalid().is_empty() {
+                text.push(char::REPLACEMENT_CHARACTER);
+                offsets.extend([offset; char::REPLACEMENT_CHARACTER.len_utf8()]);
+                offset += chunk.invalid().len();
+            }
+        }
+        offsets.push(offset);
+        Self { text, offsets }
+    }
+
+    /// Returns the offset in the line as read of the char boundary `offset`
+    /// of the decoded text. A replaced sequence starts at the offset of the
+    /// first byte of it and ends after the last one.
+    fn original_offset(&self, offset: usize) -> usize {
+        if self.offsets.is_empty() {
+            offset
+        } else {
+            self.offsets[offset]
+        }
+    }
+}
+
+/// Where a printed line, or a part of it, is in its file.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line_number: usize,
+    byte_offset: usize,
+}
+
+/// Prints the summary of a file for `-c`, `-l` and `-L`, given the number
+/// of selected lines in it.
+fn print_summary(options: &Options, state: &mut State, name: &str, count: usize) -> io::Result<()> {
+    if options.files_with_matches || options.files_without_match {
+        let listed = options.files_with_matches == (count > 0);
+        state.matched |= listed;
+        if listed {
+            writeln!(state.out, "{}", paint(options, FILENAME_COLOR, name))?;
+        }
+        return Ok(());
+    }
+    state.matched |= count > 0;
+    if options.count {
+        writeln!(state.out, "{}{}", prefix(options, name, None, ':'), count)?;
+    }
+    Ok(())
+}
+
+/// Prints `--` before a line that does not follow the last printed one.
+fn print_separator(
+    options: &Options,
+    state: &mut State,
+    last_printed: Option<usize>,
+    line_number: usize,
+) -> io::Result<()> {
+    if state.printed_group && last_printed.is_none_or(|last| last + 1 != line_number) {
+        writeln!(state.out, "{}", paint(options, SEPARATOR_COLOR, "--"))?;
+    }
+    state.printed_group = true;
+    Ok(())
+}
+
+/// Prints `prefix`, then `line` as the bytes it was read as, with a newline
+/// at the end if it has none.
+fn print_line(out: &mut impl Write, prefix: &str, line: &[u8]) -> io::Result<()> {
+    out.write_all(prefix.as_bytes())?;
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Returns `line` with the `matches` in it colored, if colors are on.
+fn highlight(options: &Options, line: &[u8], matches: &[regex::Match]) -> Vec<u8> {
+    if !options.color || options.invert_match {
+        return line.to_vec();
+    }
+    let mut highlighted = vec![];
+    let mut end = 0;
+    for found in matches.iter().filter(|found| found.start < found.end) {
+        highlighted.extend_from_slice(&line[end..found.start]);
+        highlighted.extend(paint_bytes(
+            options,
+            MATCH_COLOR,
+            &line[found.start..found.end],
+        ));
+        end = found.end;
+    }
+    highlighted.extend_from_slice(&line[end..]);
+    highlighted
+}
+
+/// Wraps `text` in the ANSI escape sequences for the SGR `code`, if colors
+/// are on.
+fn paint(options: &Options, code: &str, text: &str) -> String {
+    if options.color {
+        format!("\x1b[{}m{}\x1b[m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Like `paint`, for text that may not be valid UTF-8.
+fn paint_bytes(options: &Options, code: &str, text: &[u8]) -> Vec<u8> {
+    if options.color {
+        [format!("\x1b[{}m", code).as_bytes(), text, b"\x1b[m"].concat()
+    } else {
+        text.to_vec()
+    }
+}
+
+/// Returns the `name:`, `line:` and `offset:` prefix of an output line,
+/// with `-` instead of `:` for a context line. `position` is None for output
+/// that is not a line.
+fn prefix(options: &Options, name: &str, position: Option<Position>, separator: char) -> String {
+    let separator = paint(options, SEPARATOR_COLOR, &separator.to_string());
+    let mut prefix = String::new();
+    if options.with_filename {
+        prefix.push_str(&paint(options, FILENAME_COLOR, name));
+        prefix.push_str(&separator);
+    }
+    let Some(position) = position else {
+        return prefix;
+    };
+    if options.line_number {
+        prefix.push_str(&paint(
+            options,
+            LINE_NUMBER_COLOR,
+            &position.line_number.to_string(),
+        ));
+        prefix.push_str(&separator);
+    }
+    if options.byte_offset {
+        prefix.push_str(&paint(
+            options,
+            BYTE_OFFSET_COLOR,
+            &position.byte_offset.to_string(),
+        ));
+        prefix.push_str(&separator);
     }
+    prefix
 }
@@ -1,19 +1,28 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::parser::Node;
+use crate::charset::CharSet;
+use crate::parser::{Assertion, LookAround, Node};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TransitionKey {
     Epsilon,
     Literal(char),
-    CharClass(Vec<char>),
+    CharClass(CharSet),
     AnyChar,
+    // zero-width, followed like an epsilon transition if the assertion holds
+    Assertion(Assertion),
+    // zero-width, followed if the sub-automaton starting at the given state
+    // matches around the current position. The last two fields are the
+    // length range of the sub-automaton.
+    LookAround(LookAround, usize, usize, Option<usize>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct State {
     id: usize,
     transitions: HashMap<TransitionKey, HashSet<usize>>,
+    // epsilon targets in priority order (highest first), used by `find_nfa`
+    epsilon_order: Vec<usize>,
     is_accept: bool,
 }
 
@@ -23,18 +32,46 @@ impl State {
         transitions: HashMap<TransitionKey, HashSet<usize>>,
         is_accept: bool,
     ) -> Self {
+        let mut epsilon_order: Vec<usize> = transitions
+            .get(&TransitionKey::Epsilon)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default();
+        epsilon_order.sort();
         Self {
             id,
             transitions,
+            epsilon_order,
             is_accept,
         }
     }
 
+    /// Adds a transition. Epsilon transitions added earlier take priority
+    /// over the ones added later.
     fn add_transition(&mut self, key: TransitionKey, state_id: usize) {
-        self.transitions
-            .entry(key)
-            .or_insert(HashSet::new())
-            .insert(state_id);
+        let is_epsilon = key == TransitionKey::Epsilon;
+        let inserted = self.transitions.entry(key).or_default().insert(state_id);
+        if is_epsilon && inserted {
+            self.epsilon_order.push(state_id);
+        }
+    }
+
+    fn char_targets(&self, c: char) -> Vec<usize> {
+        let mut targets = vec![];
+        for (key, ids) in self.transitions.iter() {
+            let matched = match key {
+                TransitionKey::Literal(l) => *l == c,
+                TransitionKey::AnyChar => true,
+                TransitionKey::CharClass(set) => set.contains(c),
+                TransitionKey::Epsilon
+                | TransitionKey::Assertion(_)
+                | TransitionKey::LookAround(_, _, _, _) => false,
+            };
+            if matched {
+                targets.extend(ids.iter().copied());
+            }
+        }
+        targets.sort();
+        targets
     }
 
     #[allow(dead_code)]
@@ -48,13 +85,13 @@ impl State {
     fn get_if_only_one_epsilon_transition(&self) -> Option<usize> {
         if self.is_only_one_epsilon_transition() {
             Some(
-                self.transitions
+                *self
+                    .transitions
                     .get(&TransitionKey::Epsilon)
                     .unwrap()
                     .iter()
                     .next()
-                    .unwrap()
-                    .clone(),
+                    .unwrap(),
             )
         } else {
             None
@@ -62,7 +99,7 @@ impl State {
     }
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub struct NFA {
     start_id: usize,
@@ -107,14 +144,48 @@ fn _build_nfa(
     let mut start = generate_state(id_generator, false);
     let (mut states, _, end_id) = match node {
         Node::Literal(c) => build_literal(id_generator, &mut start, c)?,
-        Node::Or(left, right) => build_or(id_generator, &mut start, left, right)?,
+        Node::Empty => build_empty(id_generator, &mut start)?,
+        Node::Alternation(nodes) => build_alternation(id_generator, &mut start, nodes)?,
         Node::Concat(nodes) => build_concat(id_generator, &mut start, nodes)?,
-        Node::ZeroOrMore(node) => build_zero_or_more(id_generator, &mut start, node)?,
-        Node::OneOrMore(node) => build_one_or_more(id_generator, &mut start, node)?,
-        Node::ZeroOrOne(node) => build_zero_or_one(id_generator, &mut start, node)?,
-        Node::Group(node) => build_group(id_generator, &mut start, node)?,
+        Node::ZeroOrMore(node) => build_zero_or_more(id_generator, &mut start, *node, true)?,
+        Node::OneOrMore(node) => build_one_or_more(id_generator, &mut start, *node, true)?,
+        Node::ZeroOrOne(node) => build_zero_or_one(id_generator, &mut start, *node, true)?,
+        Node::Repeat(node, min, max) => {
+            build_repeat(id_generator, &mut start, *node, min, max, true)?
+        }
+        Node::Lazy(node) => match *node {
+            Node::ZeroOrMore(node) => build_zero_or_more(id_generator, &mut start, *node, false)?,
+            Node::OneOrMore(node) => build_one_or_more(id_generator, &mut start, *node, false)?,
+            Node::ZeroOrOne(node) => build_zero_or_one(id_generator, &mut start, *node, false)?,
+            Node::Repeat(node, min, max) => {
+                build_repeat(id_generator, &mut start, *node, min, max, false)?
+            }
+            node => {
+                return Err(format!(
+                    "Lazy modifier must follow a quantifier: {:?}",
+                    node
+                ))
+            }
+        },
+        Node::Group(node) | Node::NamedGroup(_, node) => {
+            build_group(id_generator, &mut start, *node)?
+        }
+        Node::Backreference(_) | Node::NamedBackreference(_) => {
+            return Err("Backreferences are not supported by the NFA engine; \
+                 use the backtracking engine"
+                .to_string())
+        }
         Node::AnyChar => build_any_char(id_generator, &mut start)?,
-        Node::CharClass(chars) => build_char_class(id_generator, &mut start, chars)?,
+        Node::CharClass(set) => build_char_class(id_generator, &mut start, set)?,
+        Node::Assertion(assertion) => build_assertion(id_generator, &mut start, assertion)?,
+        Node::LookAround(kind, node) => build_look_around(id_generator, &mut start, kind, *node)?,
+        Node::Atomic(_) => {
+            return Err(
+                "Atomic groups and possessive quantifiers are not supported by the NFA engine; \
+                 use the backtracking engine"
+                    .to_string(),
+            )
+        }
     };
 
     let start_id = start.id;
@@ -135,52 +206,51 @@ fn build_literal(
     Ok((vec![q0], q0_id, q0_id))
 }
 
-fn build_or(
+// a single split with an epsilon transition to each branch, in priority order
+fn build_alternation(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    left: Box<Node>,
-    right: Box<Node>,
+    nodes: Vec<Node>,
 ) -> Result<(Vec<State>, usize, usize), String> {
-    let left = *left;
-    let right = *right;
-    let (mut left_states, left_start_id, left_end_id) = _build_nfa(left, id_generator)?;
-    let (mut right_states, right_start_id, right_end_id) = _build_nfa(right, id_generator)?;
-
-    // start -> left_states or right_states
-    start.add_transition(TransitionKey::Epsilon, left_start_id);
-    start.add_transition(TransitionKey::Epsilon, right_start_id);
-
-    // end -> left_end_id or right_end_id
     let end = generate_state(id_generator, true);
     let end_id = end.id;
+    let mut states = vec![end];
 
-    // change is_accept to false
-    let left_end_state = left_states
-        .iter_mut()
-        .find(|state| state.id == left_end_id)
-        .unwrap();
-    left_end_state.is_accept = false;
-    left_end_state.add_transition(TransitionKey::Epsilon, end.id);
-    let right_end_state = right_states
-        .iter_mut()
-        .find(|state| state.id == right_end_id)
-        .unwrap();
-    right_end_state.is_accept = false;
-    right_end_state.add_transition(TransitionKey::Epsilon, end.id);
+    for node in nodes {
+        let (mut added_states, first_id, last_id) = _build_nfa(node, id_generator)?;
+        start.add_transition(TransitionKey::Epsilon, first_id);
 
-    // return start, left_states, right_states
-    let mut states = vec![end];
-    states.extend(left_states.into_iter());
-    states.extend(right_states.into_iter());
+        // the end of each branch goes to the common end
+        let last_state = added_states
+            .iter_mut()
+            .find(|state| state.id == last_id)
+            .unwrap();
+        last_state.is_accept = false;
+        last_state.add_transition(TransitionKey::Epsilon, end_id);
+        states.extend(added_states);
+    }
 
     Ok((states, start.id, end_id))
 }
 
+fn build_empty(
+    id_generator: &mut IDGenerator,
+    start: &mut State,
+) -> Result<(Vec<State>, usize, usize), String> {
+    let end = generate_state(id_generator, true);
+    start.add_transition(TransitionKey::Epsilon, end.id);
+    let end_id = end.id;
+    Ok((vec![end], start.id, end_id))
+}
+
 fn build_concat(
     id_generator: &mut IDGenerator,
     start: &mut State,
     nodes: Vec<Node>,
 ) -> Result<(Vec<State>, usize, usize), String> {
+    if nodes.is_empty() {
+        return build_empty(id_generator, start);
+    }
     let start_id = start.id;
 
     let mut prev_end_id = start_id;
@@ -258,32 +328,106 @@ fn build_concat(
 fn build_zero_or_more(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    node: Box<Node>,
+    node: Node,
+    greedy: bool,
 ) -> Result<(Vec<State>, usize, usize), String> {
+    if node.length_range().0 == 0 {
+        return build_nullable_zero_or_more(id_generator, start, node, greedy);
+    }
+
     // start is not accept
     start.is_accept = false;
 
     let mut end_state = generate_state(id_generator, true);
-    let (mut added_states, _first_id, _end_id) = _build_nfa(*node, id_generator)?;
+    let (mut added_states, _first_id, _end_id) = _build_nfa(node, id_generator)?;
 
-    // start -> first_state
-    start.add_transition(TransitionKey::Epsilon, _first_id);
-    start.add_transition(TransitionKey::Epsilon, end_state.id);
+    // start -> first_state or end_state
+    // (greedy prefers entering the loop, lazy prefers leaving it)
+    let _end_state = added_states
+        .iter_mut()
+        .find(|state| state.id == _end_id)
+        .unwrap();
+    _end_state.is_accept = false;
+    if greedy {
+        start.add_transition(TransitionKey::Epsilon, _first_id);
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+
+        // end_state -> first_state
+        end_state.add_transition(TransitionKey::Epsilon, _first_id);
+
+        // _end_state -> first_state or end_state
+        _end_state.add_transition(TransitionKey::Epsilon, _first_id);
+        _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
+    } else {
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+        start.add_transition(TransitionKey::Epsilon, _first_id);
+
+        // _end_state -> end_state or first_state
+        _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
+        _end_state.add_transition(TransitionKey::Epsilon, _first_id);
+    }
+
+    let end_id = end_state.id;
+    let mut states = vec![end_state];
+    states.extend(added_states);
+
+    Ok((states, start.id, end_id))
+}
+
+/// Builds `node*` for a node that can match the empty string. As in Perl, an
+/// iteration that matches nothing ends the loop. The node is built twice:
+/// each iteration starts in the first copy and moves to the second one when
+/// it consumes a character, and only the end of the second copy loops back.
+fn build_nullable_zero_or_more(
+    id_generator: &mut IDGenerator,
+    start: &mut State,
+    node: Node,
+    greedy: bool,
+) -> Result<(Vec<State>, usize, usize), String> {
+    start.is_accept = false;
 
-    // end_state -> first_state
-    end_state.add_transition(TransitionKey::Epsilon, _first_id);
+    let end_state = generate_state(id_generator, true);
+    let (mut empty_states, empty_first_id, empty_end_id) = _build_nfa(node.clone(), id_generator)?;
+    let (mut added_states, _first_id, _end_id) = _build_nfa(node, id_generator)?;
+
+    // both copies are built in the same order, so their ids differ by the
+    // same offset
+    let offset = _first_id - empty_first_id;
+    for state in empty_states.iter_mut() {
+        for (key, ids) in state.transitions.iter_mut() {
+            if matches!(
+                key,
+                TransitionKey::Literal(_) | TransitionKey::AnyChar | TransitionKey::CharClass(_)
+            ) {
+                *ids = ids.iter().map(|id| id + offset).collect();
+            }
+        }
+        if state.id == empty_end_id {
+            state.is_accept = false;
+            state.add_transition(TransitionKey::Epsilon, end_state.id);
+        }
+    }
 
-    // _end_state -> end_state
     let _end_state = added_states
         .iter_mut()
         .find(|state| state.id == _end_id)
         .unwrap();
-    _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
-    _end_state.add_transition(TransitionKey::Epsilon, _first_id);
     _end_state.is_accept = false;
+    if greedy {
+        start.add_transition(TransitionKey::Epsilon, empty_first_id);
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+        _end_state.add_transition(TransitionKey::Epsilon, empty_first_id);
+        _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
+    } else {
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+        start.add_transition(TransitionKey::Epsilon, empty_first_id);
+        _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
+        _end_state.add_transition(TransitionKey::Epsilon, empty_first_id);
+    }
 
     let end_id = end_state.id;
     let mut states = vec![end_state];
+    states.extend(empty_states);
     states.extend(added_states);
 
     Ok((states, start.id, end_id))
@@ -292,9 +436,21 @@ fn build_zero_or_more(
 fn build_one_or_more(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    node: Box<Node>,
+    node: Node,
+    greedy: bool,
 ) -> Result<(Vec<State>, usize, usize), String> {
-    let (mut added_states, _first_id, _end_id) = _build_nfa(*node, id_generator)?;
+    // built as `node node*`, so that the loop ends like a nullable `*`
+    if node.length_range().0 == 0 {
+        let star = Node::ZeroOrMore(Box::new(node.clone()));
+        let star = if greedy {
+            star
+        } else {
+            Node::Lazy(Box::new(star))
+        };
+        return build_concat(id_generator, start, vec![node, star]);
+    }
+
+    let (mut added_states, _first_id, _end_id) = _build_nfa(node, id_generator)?;
 
     start.add_transition(TransitionKey::Epsilon, _first_id);
 
@@ -302,9 +458,21 @@ fn build_one_or_more(
         .iter_mut()
         .find(|state| state.id == _end_id)
         .unwrap();
-    child_end_state.is_accept = true;
+    if greedy {
+        child_end_state.is_accept = true;
+        child_end_state.add_transition(TransitionKey::Epsilon, _first_id);
+        let end_id = child_end_state.id;
+        return Ok((added_states, start.id, end_id));
+    }
+
+    // lazy: the exit must be added before the loop so that it has priority,
+    // so it gets its own state instead of sharing child_end_state
+    let end_state = generate_state(id_generator, true);
+    child_end_state.is_accept = false;
+    child_end_state.add_transition(TransitionKey::Epsilon, end_state.id);
     child_end_state.add_transition(TransitionKey::Epsilon, _first_id);
-    let end_id = child_end_state.id;
+    let end_id = end_state.id;
+    added_states.push(end_state);
 
     Ok((added_states, start.id, end_id))
 }
@@ -312,22 +480,73 @@ fn build_one_or_more(
 fn build_zero_or_one(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    node: Box<Node>,
+    node: Node,
+    greedy: bool,
 ) -> Result<(Vec<State>, usize, usize), String> {
-    let (added_states, _first_id, _end_id) = _build_nfa(*node, id_generator)?;
+    let (mut added_states, _first_id, _end_id) = _build_nfa(node, id_generator)?;
 
-    start.add_transition(TransitionKey::Epsilon, _first_id);
-    start.add_transition(TransitionKey::Epsilon, _end_id);
+    // the skip goes to a new end state rather than to the end of the node,
+    // which can loop back into the node (e.g. `(ab+)?`)
+    let end_state = generate_state(id_generator, true);
+    let _end_state = added_states
+        .iter_mut()
+        .find(|state| state.id == _end_id)
+        .unwrap();
+    _end_state.is_accept = false;
+    _end_state.add_transition(TransitionKey::Epsilon, end_state.id);
 
-    Ok((added_states, start.id, _end_id))
+    if greedy {
+        start.add_transition(TransitionKey::Epsilon, _first_id);
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+    } else {
+        start.add_transition(TransitionKey::Epsilon, end_state.id);
+        start.add_transition(TransitionKey::Epsilon, _first_id);
+    }
+
+    let end_id = end_state.id;
+    added_states.push(end_state);
+
+    Ok((added_states, start.id, end_id))
+}
+
+/// Builds `node{min,max}` by expanding it into `min` copies of `node`
+/// followed by `max - min` optional copies (or a `*` loop if `max` is None).
+fn build_repeat(
+    id_generator: &mut IDGenerator,
+    start: &mut State,
+    node: Node,
+    min: usize,
+    max: Option<usize>,
+    greedy: bool,
+) -> Result<(Vec<State>, usize, usize), String> {
+    let quantify = |node: Node| {
+        if greedy {
+            node
+        } else {
+            Node::Lazy(Box::new(node))
+        }
+    };
+
+    let mut nodes = vec![node.clone(); min];
+    match max {
+        Some(max) => {
+            for _ in min..max {
+                nodes.push(quantify(Node::ZeroOrOne(Box::new(node.clone()))));
+            }
+        }
+        None => nodes.push(quantify(Node::ZeroOrMore(Box::new(node)))),
+    }
+
+    // x{0} matches only the empty string
+    build_concat(id_generator, start, nodes)
 }
 
 fn build_group(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    node: Box<Node>,
+    node: Node,
 ) -> Result<(Vec<State>, usize, usize), String> {
-    let (added_states, _first_id, _end_id) = _build_nfa(*node, id_generator)?;
+    let (added_states, _first_id, _end_id) = _build_nfa(node, id_generator)?;
     start.add_transition(TransitionKey::Epsilon, _first_id);
     Ok((added_states, start.id, _end_id))
 }
@@ -347,16 +566,59 @@ fn build_any_char(
 fn build_char_class(
     id_generator: &mut IDGenerator,
     start: &mut State,
-    chars: Vec<char>,
+    set: CharSet,
 ) -> Result<(Vec<State>, usize, usize), String> {
     let q0 = generate_state(id_generator, true);
     let q0_id = q0.id;
 
-    start.add_transition(TransitionKey::CharClass(chars), q0_id);
+    start.add_transition(TransitionKey::CharClass(set), q0_id);
 
     Ok((vec![q0], q0_id, q0_id))
 }
 
+fn build_assertion(
+    id_generator: &mut IDGenerator,
+    start: &mut State,
+    assertion: Assertion,
+) -> Result<(Vec<State>, usize, usize), String> {
+    let q0 = generate_state(id_generator, true);
+    let q0_id = q0.id;
+
+    start.add_transition(TransitionKey::Assertion(assertion), q0_id);
+
+    Ok((vec![q0], q0_id, q0_id))
+}
+
+fn build_look_around(
+    id_generator: &mut IDGenerator,
+    start: &mut State,
+    kind: LookAround,
+    node: Node,
+) -> Result<(Vec<State>, usize, usize), String> {
+    let (min_len, max_len) = node.length_range();
+    if kind.is_behind() && max_len.is_none() {
+        return Err(
+            "Lookbehind of unbounded length is not supported by the NFA engine; \
+             use the backtracking engine"
+                .to_string(),
+        );
+    }
+
+    // the sub-automaton lives in the same NFA, but it is only reachable
+    // through the lookaround transition
+    let (mut states, sub_start_id, _) = _build_nfa(node, id_generator)?;
+    let q0 = generate_state(id_generator, true);
+    let q0_id = q0.id;
+
+    start.add_transition(
+        TransitionKey::LookAround(kind, sub_start_id, min_len, max_len),
+        q0_id,
+    );
+
+    states.push(q0);
+    Ok((states, q0_id, q0_id))
+}
+
 fn build_states(states: Vec<State>) -> HashMap<usize, State> {
     let mut map = HashMap::new();
     for state in states {
@@ -372,8 +634,9 @@ pub fn match_nfa(nfa: &NFA, input: &str) -> Result<bool, String> {
         nfa.start_id,
         &mut InputWithIndex {
             index: 0,
-            input: input.to_string(),
+            chars: input.chars().collect(),
         },
+        &mut HashSet::new(),
     )?;
     match result {
         MatchResult::Match => Ok(true),
@@ -388,21 +651,27 @@ enum MatchResult {
     NoMatch,
 }
 
+// the input as chars, collected once so that each step is O(1)
 #[derive(Debug)]
 struct InputWithIndex {
     index: usize,
-    input: String,
+    chars: Vec<char>,
 }
 
 #[allow(dead_code)]
 impl InputWithIndex {
     fn next(&mut self) -> Option<char> {
-        let result = self.input.chars().nth(self.index);
+        let result = self.peek();
         self.index += 1;
         result
     }
     fn peek(&self) -> Option<char> {
-        self.input.chars().nth(self.index)
+        self.chars.get(self.index).copied()
+    }
+    fn prev(&self) -> Option<char> {
+        self.index
+            .checked_sub(1)
+            .and_then(|index| self.chars.get(index).copied())
     }
     fn set_index(&mut self, index: usize) {
         self.index = index;
@@ -416,9 +685,17 @@ fn _match_nfa(
     nfa: &NFA,
     current_state_id: usize,
     input: &mut InputWithIndex,
+    visited: &mut HashSet<(usize, usize)>,
 ) -> Result<MatchResult, String> {
+    // the result only depends on the state and the position, so each pair is
+    // explored once. This also stops at a loop of epsilon transitions, as
+    // built for a nullable loop such as (a*)*.
+    if !visited.insert((current_state_id, input.index)) {
+        return Ok(MatchResult::NoMatch);
+    }
+
     if input.is_end() {
-        let closure = epsilon_closure(nfa, current_state_id)?;
+        let closure = epsilon_closure_at(nfa, current_state_id, input)?;
         for state_id in closure {
             if nfa.states.get(&state_id).unwrap().is_accept {
                 return Ok(MatchResult::Match);
@@ -429,7 +706,7 @@ fn _match_nfa(
 
     if let Some(c) = input.peek() {
         // check transition
-        let _next_states = nfa.states.get(&current_state_id).and_then(|state| {
+        let _next_states = nfa.states.get(&current_state_id).map(|state| {
             let mut next_state_ids = HashSet::new();
             // check literal transition
             if let Some(transitions) = state.transitions.get(&TransitionKey::Literal(c)) {
@@ -442,23 +719,20 @@ fn _match_nfa(
             // check char class transition
             let mut adapted_char_class_transitions = HashSet::new();
             for transition in state.transitions.iter() {
-                match transition.0 {
-                    TransitionKey::CharClass(chars) => {
-                        if chars.contains(&c) {
-                            adapted_char_class_transitions.extend(transition.1.iter().cloned());
-                        }
+                if let TransitionKey::CharClass(set) = transition.0 {
+                    if set.contains(c) {
+                        adapted_char_class_transitions.extend(transition.1.iter().cloned());
                     }
-                    _ => {}
                 }
             }
             next_state_ids.extend(adapted_char_class_transitions);
-            Some(next_state_ids)
+            next_state_ids
         });
 
         // check epsilon transition
-        let closure = epsilon_closure(nfa, current_state_id)?;
+        let closure = epsilon_closure_at(nfa, current_state_id, input)?;
         let next_states: HashSet<usize> = _next_states
-            .unwrap_or(HashSet::new())
+            .unwrap_or_default()
             .union(&closure)
             .cloned()
             .collect();
@@ -471,7 +745,7 @@ fn _match_nfa(
 
         if next_states.is_empty() {
             input.next();
-            return _match_nfa(nfa, nfa.start_id, input);
+            return _match_nfa(nfa, nfa.start_id, input, visited);
         }
 
         for (next_state_id, is_epsilon) in next_states {
@@ -485,7 +759,7 @@ fn _match_nfa(
                 if !is_epsilon {
                     input.next();
                 }
-                let result = _match_nfa(nfa, next_state_id.clone(), input)?;
+                let result = _match_nfa(nfa, next_state_id, input, visited)?;
                 match result {
                     MatchResult::Match => return Ok(MatchResult::Match),
                     MatchResult::NoMatch => {
@@ -500,33 +774,231 @@ fn _match_nfa(
     Ok(MatchResult::NoMatch)
 }
 
+#[allow(dead_code)]
 fn epsilon_closure(nfa: &NFA, current_state_id: usize) -> Result<HashSet<usize>, String> {
     let mut visited = HashSet::new();
-    _epsilon_closure(nfa, current_state_id, &mut visited)?;
+    _epsilon_closure(nfa, current_state_id, None, &mut visited)?;
+    Ok(visited)
+}
+
+// same as `epsilon_closure`, but also follows the zero-width transitions
+// that hold at the current position of `input`
+fn epsilon_closure_at(
+    nfa: &NFA,
+    current_state_id: usize,
+    input: &InputWithIndex,
+) -> Result<HashSet<usize>, String> {
+    let mut visited = HashSet::new();
+    let at = Cursor {
+        chars: &input.chars,
+        index: input.index,
+    };
+    _epsilon_closure(nfa, current_state_id, Some(at), &mut visited)?;
     Ok(visited)
 }
 
 fn _epsilon_closure(
     nfa: &NFA,
     current_state_id: usize,
+    at: Option<Cursor>,
     visited: &mut HashSet<usize>,
 ) -> Result<(), String> {
     let current_state = nfa.states.get(&current_state_id).unwrap();
-    let binding = HashSet::new();
-    let epsilon_states = current_state
-        .transitions
-        .get(&TransitionKey::Epsilon)
-        .unwrap_or(&binding);
-    for next_state_id in epsilon_states {
+    let mut next_state_ids = current_state.epsilon_order.clone();
+    if let Some(at) = at {
+        next_state_ids.extend(zero_width_targets(nfa, current_state, at));
+    }
+    for next_state_id in next_state_ids {
         if visited.contains(&next_state_id) {
             continue;
         }
-        visited.insert(next_state_id.clone());
-        _epsilon_closure(nfa, next_state_id.clone(), visited)?;
+        visited.insert(next_state_id);
+        _epsilon_closure(nfa, next_state_id, at, visited)?;
     }
     Ok(())
 }
 
+// a position in the input, used to evaluate zero-width transitions
+#[derive(Debug, Clone, Copy)]
+struct Cursor<'a> {
+    chars: &'a [char],
+    index: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+}
+
+// returns the targets of the assertions and lookarounds of `state` that hold at `at`
+fn zero_width_targets(nfa: &NFA, state: &State, at: Cursor) -> Vec<usize> {
+    let mut targets = vec![];
+    for (key, ids) in state.transitions.iter() {
+        let holds = match key {
+            TransitionKey::Assertion(assertion) => assertion.holds(at.chars, at.index),
+            TransitionKey::LookAround(kind, start_id, min_len, max_len) => {
+                look_around_holds(nfa, *kind, *start_id, *min_len, *max_len, at)
+            }
+            _ => continue,
+        };
+        if holds {
+            targets.extend(ids.iter().copied());
+        }
+    }
+    targets.sort();
+    targets
+}
+
+fn look_around_holds(
+    nfa: &NFA,
+    kind: LookAround,
+    start_id: usize,
+    min_len: usize,
+    max_len: Option<usize>,
+    at: Cursor,
+) -> bool {
+    let found = if kind.is_behind() {
+        // try every start that can end exactly here
+        let max_len = max_len.unwrap_or(at.index).min(at.index);
+        (min_len..=max_len)
+            .any(|len| matches_from(nfa, start_id, at.chars, at.index - len, Some(at.index)))
+    } else {
+        matches_from(nfa, start_id, at.chars, at.index, None)
+    };
+    found != kind.is_negative()
+}
+
+// runs the automaton from `start_id` anchored at `from`, and returns whether
+// it reaches an accept state (exactly at `to`, if given)
+fn matches_from(
+    nfa: &NFA,
+    start_id: usize,
+    chars: &[char],
+    from: usize,
+    to: Option<usize>,
+) -> bool {
+    let mut current = ThreadList::default();
+    let at = Cursor { chars, index: from };
+    add_thread(nfa, &mut current, start_id, from, at);
+    let mut index = from;
+    loop {
+        let accepted = current
+            .threads
+            .iter()
+            .any(|thread| nfa.states[&thread.state_id].is_accept);
+        if accepted && to.is_none_or(|to| to == index) {
+            return true;
+        }
+        if current.threads.is_empty() || to.is_some_and(|to| index >= to) {
+            return false;
+        }
+        let Some(&c) = chars.get(index) else {
+            return false;
+        };
+
+        index += 1;
+        let at = Cursor { chars, index };
+        let mut next = ThreadList::default();
+        for thread in current.threads {
+            for next_state_id in nfa.states[&thread.state_id].char_targets(c) {
+                add_thread(nfa, &mut next, next_state_id, from, at);
+            }
+        }
+        current = next;
+    }
+}
+
+/// Finds the leftmost match in `input` and returns its byte range.
+///
+/// Unlike `match_nfa`, all threads are simulated in lockstep (Pike VM), and
+/// epsilon transitions are followed in priority order so that greedy
+/// quantifiers prefer the longer path and lazy quantifiers the shorter one.
+#[allow(dead_code)]
+pub fn find_nfa(nfa: &NFA, input: &str) -> Option<(usize, usize)> {
+    find_nfa_at(nfa, input, 0)
+}
+
+/// Like `find_nfa`, but only finds matches that start at or after the byte
+/// offset `start`. The text before `start` is still seen by assertions and
+/// lookbehind.
+#[allow(dead_code)]
+pub fn find_nfa_at(nfa: &NFA, input: &str, start: usize) -> Option<(usize, usize)> {
+    let chars: Vec<char> = input.chars().collect();
+    let offsets: Vec<usize> = input
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(input.len()))
+        .collect();
+
+    let mut matched = None;
+    let mut current = ThreadList::default();
+    let first = offsets.partition_point(|&pos| pos < start);
+    for (i, &pos) in offsets.iter().enumerate().skip(first) {
+        let at = Cursor {
+            chars: &chars,
+            index: i,
+        };
+        let c = at.next();
+        if matched.is_none() {
+            // a new thread starting here has the lowest priority
+            add_thread(nfa, &mut current, nfa.start_id, pos, at);
+        }
+        if current.threads.is_empty() {
+            break;
+        }
+
+        let mut next = ThreadList::default();
+        for thread in current.threads {
+            let state = nfa.states.get(&thread.state_id).unwrap();
+            if state.is_accept {
+                // threads after this one have lower priority
+                matched = Some((thread.start, pos));
+                break;
+            }
+            if let Some(c) = c {
+                let at = Cursor {
+                    chars: &chars,
+                    index: i + 1,
+                };
+                for next_state_id in state.char_targets(c) {
+                    add_thread(nfa, &mut next, next_state_id, thread.start, at);
+                }
+            }
+        }
+        current = next;
+    }
+    matched
+}
+
+#[derive(Debug)]
+struct Thread {
+    state_id: usize,
+    start: usize,
+}
+
+#[derive(Debug, Default)]
+struct ThreadList {
+    threads: Vec<Thread>,
+    visited: HashSet<usize>,
+}
+
+fn add_thread(nfa: &NFA, list: &mut ThreadList, state_id: usize, start: usize, at: Cursor) {
+    if !list.visited.insert(state_id) {
+        return;
+    }
+    let state = nfa.states.get(&state_id).unwrap();
+    for next_state_id in state.epsilon_order.iter() {
+        add_thread(nfa, list, *next_state_id, start, at);
+    }
+    for next_state_id in zero_width_targets(nfa, state, at) {
+        add_thread(nfa, list, next_state_id, start, at);
+    }
+    // the state itself goes after the states reachable by epsilon, so that an
+    // accept state with a loop back (e.g. `a+`) keeps looping first
+    list.threads.push(Thread { state_id, start });
+}
+
 impl NFA {
     #[allow(dead_code)]
     pub fn to_dot(&self) -> String {
@@ -546,7 +1018,7 @@ impl NFA {
             }
         }
 
-        body.push_str(&format!("\trankdir=LR\n"));
+        body.push_str("\trankdir=LR\n");
         body.push_str(&format!(
             "\tnode [shape=doublecircle]; {};\n",
             accept_states
@@ -555,9 +1027,23 @@ impl NFA {
                 .collect::<Vec<String>>()
                 .join(" ")
         ));
-        body.push_str(&format!("\tnode [shape=circle];\n"));
+        body.push_str("\tnode [shape=circle];\n");
         for state in self.states.values() {
+            // epsilon transitions are labeled with their priority when it matters
+            if state.epsilon_order.len() > 1 {
+                for (priority, next_state_id) in state.epsilon_order.iter().enumerate() {
+                    body.push_str(&format!(
+                        "\t{} -> {} [label=\"ε{}\"]\n",
+                        state.id,
+                        next_state_id,
+                        priority + 1
+                    ));
+                }
+            }
             for (c, next_states) in state.transitions.iter() {
+                if *c == TransitionKey::Epsilon && state.epsilon_order.len() > 1 {
+                    continue;
+                }
                 for next_state_id in next_states {
                     body.push_str(&format!(
                         "\t{} -> {} [label=\"{}\"]\n",
@@ -566,9 +1052,14 @@ impl NFA {
                         match c {
                             TransitionKey::Literal(c) => format!("{}", c),
                             TransitionKey::Epsilon => "ε".to_string(),
-                            TransitionKey::CharClass(chars) =>
-                                format!("[{}]", chars.iter().collect::<String>()),
+                            TransitionKey::CharClass(set) => format!("[{}]", set)
+                                .replace('\\', "\\\\")
+                                .replace('"', "\\\""),
                             TransitionKey::AnyChar => "AnyChar".to_string(),
+                            TransitionKey::Assertion(assertion) =>
+                                assertion.to_string().replace('\\', "\\\\"),
+                            TransitionKey::LookAround(kind, start_id, _, _) =>
+                                format!("{} {})", kind, start_id),
                         }
                     ));
                 }
@@ -581,6 +1072,8 @@ impl NFA {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
 
     #[test]
     fn test_epsilon_closure() {
@@ -694,10 +1187,10 @@ mod tests {
         assert_eq!(match_nfa(&nfa, "b"), Ok(false));
 
         // a|b
-        let nfa = build_nfa(Node::Or(
-            Box::new(Node::Literal('a')),
-            Box::new(Node::Literal('b')),
-        ))
+        let nfa = build_nfa(Node::Alternation(vec![
+            Node::Literal('a'),
+            Node::Literal('b'),
+        ]))
         .unwrap();
         assert_eq!(match_nfa(&nfa, "a"), Ok(true));
         assert_eq!(match_nfa(&nfa, "b"), Ok(true));
@@ -706,10 +1199,10 @@ mod tests {
         assert_eq!(match_nfa(&nfa, "bb"), Ok(true));
 
         // ab|cd
-        let nfa = build_nfa(Node::Or(
-            Box::new(Node::Concat(vec![Node::Literal('a'), Node::Literal('b')])),
-            Box::new(Node::Concat(vec![Node::Literal('c'), Node::Literal('d')])),
-        ))
+        let nfa = build_nfa(Node::Alternation(vec![
+            Node::Concat(vec![Node::Literal('a'), Node::Literal('b')]),
+            Node::Concat(vec![Node::Literal('c'), Node::Literal('d')]),
+        ]))
         .unwrap();
         assert_eq!(match_nfa(&nfa, "ab"), Ok(true));
         assert_eq!(match_nfa(&nfa, "cd"), Ok(true));
@@ -787,10 +1280,10 @@ mod tests {
         assert_eq!(match_nfa(&nfa, "b"), Ok(false));
 
         // (a|b)
-        let nfa = build_nfa(Node::Group(Box::new(Node::Or(
-            Box::new(Node::Literal('a')),
-            Box::new(Node::Literal('b')),
-        ))))
+        let nfa = build_nfa(Node::Group(Box::new(Node::Alternation(vec![
+            Node::Literal('a'),
+            Node::Literal('b'),
+        ]))))
         .unwrap();
         assert_eq!(match_nfa(&nfa, "a"), Ok(true));
         assert_eq!(match_nfa(&nfa, "b"), Ok(true));
@@ -843,7 +1336,7 @@ mod tests {
         assert_eq!(match_nfa(&nfa, "c"), Ok(false));
 
         // [a-c]
-        let nfa = build_nfa(Node::CharClass(vec!['a', 'b', 'c'])).unwrap();
+        let nfa = build_nfa(Node::CharClass(vec!['a', 'b', 'c'].into())).unwrap();
         assert_eq!(match_nfa(&nfa, "a"), Ok(true));
         assert_eq!(match_nfa(&nfa, "b"), Ok(true));
         assert_eq!(match_nfa(&nfa, "c"), Ok(true));
@@ -856,7 +1349,7 @@ mod tests {
 
         // [a-c]d
         let nfa = build_nfa(Node::Concat(vec![
-            Node::CharClass(vec!['a', 'b', 'c']),
+            Node::CharClass(vec!['a', 'b', 'c'].into()),
             Node::Literal('d'),
         ]))
         .unwrap();
@@ -865,4 +1358,180 @@ mod tests {
         assert_eq!(match_nfa(&nfa, "cd"), Ok(true));
         assert_eq!(match_nfa(&nfa, "dd"), Ok(false));
     }
+
+    fn find(pattern: &str, input: &str) -> Option<(usize, usize)> {
+        let nfa = build_nfa(parse(lex(pattern).unwrap()).unwrap()).unwrap();
+        find_nfa(&nfa, input)
+    }
+
+    #[test]
+    fn test_find_nfa() {
+        assert_eq!(find("a", "a"), Some((0, 1)));
+        assert_eq!(find("ab", "aab"), Some((1, 3)));
+        assert_eq!(find("ab", "ba"), None);
+        assert_eq!(find("b|ab", "xab"), Some((1, 3)));
+        assert_eq!(find("a*", "bbb"), Some((0, 0)));
+        assert_eq!(find("x*", ""), Some((0, 0)));
+        assert_eq!(find("[a-c]d", "xxbd"), Some((2, 4)));
+        assert_eq!(find(".bc", "bcabc"), Some((2, 5)));
+        // byte offsets
+        assert_eq!(find("b", "あb"), Some((3, 4)));
+
+        // alternation, with empty branches
+        assert_eq!(find("a|b|c", "xc"), Some((1, 2)));
+        assert_eq!(find("ab|a|abc", "abc"), Some((0, 2)));
+        assert_eq!(find("a|", "ba"), Some((0, 0)));
+        assert_eq!(find("x(a|)b", "xb xab"), Some((0, 2)));
+        assert_eq!(find("x(|a)", "xa"), Some((0, 1)));
+        assert_eq!(find("x(a|)", "xa"), Some((0, 2)));
+        assert_eq!(find("", "abc"), Some((0, 0)));
+        assert_eq!(find("()", ""), Some((0, 0)));
+        assert_eq!(find("a()b", "ab"), Some((0, 2)));
+
+        // greedy
+        assert_eq!(find("a*", "aaa"), Some((0, 3)));
+        assert_eq!(find("a+", "baaa"), Some((1, 4)));
+        assert_eq!(find("a?", "aa"), Some((0, 1)));
+        assert_eq!(find("(ab)*", "ababx"), Some((0, 4)));
+        assert_eq!(find("\".*\"", "\"a\", \"b\""), Some((0, 8)));
+
+        // lazy
+        assert_eq!(find("a*?", "aaa"), Some((0, 0)));
+        assert_eq!(find("a+?", "baaa"), Some((1, 2)));
+        assert_eq!(find("a??", "aa"), Some((0, 0)));
+        assert_eq!(find("a*?b", "aab"), Some((0, 3)));
+        assert_eq!(find("\".*?\"", "\"a\", \"b\""), Some((0, 3)));
+        assert_eq!(find("(ab)+?", "ababx"), Some((0, 2)));
+
+        // counted repetition
+        assert_eq!(find("a{2}", "aaa"), Some((0, 2)));
+        assert_eq!(find("a{2}", "a"), None);
+        assert_eq!(find("a{2,}", "aaaa"), Some((0, 4)));
+        assert_eq!(find("a{1,3}", "aaaa"), Some((0, 3)));
+        assert_eq!(find("a{1,3}?", "aaaa"), Some((0, 1)));
+        assert_eq!(find("a{2,}?", "aaaa"), Some((0, 2)));
+        assert_eq!(find("ba{0}", "ba"), Some((0, 1)));
+        assert_eq!(find("[ab]{3}", "xabba"), Some((1, 4)));
+
+        // skipping an optional node does not fall back into its loop
+        assert_eq!(find("(ab+)?", "bbb"), Some((0, 0)));
+        assert_eq!(find("(a+)??", "aaac"), Some((0, 0)));
+        assert_eq!(find("c(ab+)?$", "cbb"), None);
+        assert_eq!(find("(ab+){0,2}", "bbb"), Some((0, 0)));
+        assert_eq!(find("(ab+){0,2}c", "abbabc"), Some((0, 6)));
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let nfa = build_nfa(Node::Concat(vec![
+            Node::Assertion(Assertion::WordBoundary),
+            Node::Literal('i'),
+            Node::Literal('d'),
+            Node::Assertion(Assertion::WordBoundary),
+        ]))
+        .unwrap();
+        assert_eq!(match_nfa(&nfa, "id"), Ok(true));
+        assert_eq!(match_nfa(&nfa, "user id=1"), Ok(true));
+        assert_eq!(match_nfa(&nfa, "width"), Ok(false));
+        assert_eq!(match_nfa(&nfa, "valid"), Ok(false));
+        assert_eq!(match_nfa(&nfa, "ids"), Ok(false));
+
+        assert_eq!(find("\\bid\\b", "id"), Some((0, 2)));
+        assert_eq!(find("\\bid\\b", "valid id"), Some((6, 8)));
+        assert_eq!(find("\\bid\\b", "width valid"), None);
+        assert_eq!(find("\\Bid\\B", "id width"), Some((4, 6)));
+        assert_eq!(find("\\Bid", "id valid"), Some((6, 8)));
+        assert_eq!(find("\\b", ""), None);
+        assert_eq!(find("\\B", ""), Some((0, 0)));
+        assert_eq!(find("\\b", "  ab"), Some((2, 2)));
+        assert_eq!(find("a\\b", "aa a"), Some((1, 2)));
+        assert_eq!(find("\\bé", "café é"), Some((6, 8)));
+    }
+
+    #[test]
+    fn test_nullable_loop() {
+        for pattern in [
+            "(a*)*", "(a*)+", "(a?)*b", "(|a)*", "()*", "(^)*a", "(a*){2,}",
+        ] {
+            let nfa = build_nfa(parse(lex(pattern).unwrap()).unwrap()).unwrap();
+            assert_eq!(match_nfa(&nfa, "aab"), Ok(true), "{}", pattern);
+        }
+        let nfa = build_nfa(parse(lex("(a*)*c").unwrap()).unwrap()).unwrap();
+        assert_eq!(match_nfa(&nfa, "aaaaaaaaaaaaaaaaaaaab"), Ok(false));
+
+        assert_eq!(find("(a*)*", "aab"), Some((0, 2)));
+        assert_eq!(find("(a*)+b", "aab"), Some((0, 3)));
+        assert_eq!(find("(a*)*c", "aab"), None);
+        // an iteration that matches nothing ends the loop, as in Perl
+        assert_eq!(find("(|a)+", "aa"), Some((0, 0)));
+        assert_eq!(find("(|b)*", "bb"), Some((0, 0)));
+        assert_eq!(find("(|a)+b", "aab"), Some((0, 3)));
+        assert_eq!(find("(a|)+", "aa"), Some((0, 2)));
+        assert_eq!(find("(b?|.)*", "bba"), Some((0, 2)));
+        assert_eq!(find("([ab]*?)+a", "baba"), Some((0, 2)));
+        assert_eq!(find("()*", "x"), Some((0, 0)));
+        assert_eq!(find("(\\b)+a", "a"), Some((0, 1)));
+        assert_eq!(find("^*a", "ba"), Some((1, 2)));
+        assert_eq!(find("^+a", "ba"), None);
+    }
+
+    #[test]
+    fn test_anchor() {
+        let nfa = build_nfa(Node::Concat(vec![
+            Node::Assertion(Assertion::StartText),
+            Node::Literal('a'),
+        ]))
+        .unwrap();
+        assert_eq!(match_nfa(&nfa, "ab"), Ok(true));
+        assert_eq!(match_nfa(&nfa, "ba"), Ok(false));
+
+        assert_eq!(find("^a", "aa"), Some((0, 1)));
+        assert_eq!(find("^a", "ba"), None);
+        assert_eq!(find("a$", "aa"), Some((1, 2)));
+        assert_eq!(find("^$", ""), Some((0, 0)));
+        assert_eq!(find("^b", "a\nb"), None);
+        assert_eq!(find("(?m)^b", "a\nb"), Some((2, 3)));
+        assert_eq!(find("(?m)a$", "a\nb"), Some((0, 1)));
+        assert_eq!(find("(?m)a$", "a\r\nb"), None);
+        assert_eq!(find("(?mR)a$", "a\r\nb"), Some((0, 1)));
+        assert_eq!(find("(?mR)^b", "a\rb"), Some((2, 3)));
+        assert_eq!(find("(?mR)$", "a\r\n"), Some((1, 1)));
+        assert_eq!(find("\\Aa\\z", "a"), Some((0, 1)));
+        assert_eq!(find("(?m)\\Ab", "a\nb"), None);
+        assert_eq!(find("a\\z", "a\n"), None);
+        assert_eq!(find("a\\Z", "a\n"), Some((0, 1)));
+        assert_eq!(find("a\\Z", "a\nb"), None);
+        assert_eq!(find("(?R)a\\Z", "a\r\n"), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_look_around() {
+        let nfa = build_nfa(parse(lex("foo(?=bar)").unwrap()).unwrap()).unwrap();
+        assert_eq!(match_nfa(&nfa, "foobar"), Ok(true));
+        assert_eq!(match_nfa(&nfa, "foobaz"), Ok(false));
+        let nfa = build_nfa(parse(lex("(?<=a)b").unwrap()).unwrap()).unwrap();
+        assert_eq!(match_nfa(&nfa, "ab"), Ok(true));
+        assert_eq!(match_nfa(&nfa, "cb"), Ok(false));
+
+        assert_eq!(find("foo(?=bar)", "foobaz foobar"), Some((7, 10)));
+        assert_eq!(find("foo(?!bar)", "foobar foobaz"), Some((7, 10)));
+        assert_eq!(find("(?!test)[a-z]+", "test"), Some((1, 4)));
+        assert_eq!(find("(?<=\\$)[0-9]+", "12 $34"), Some((4, 6)));
+        assert_eq!(find("(?<!\\$)\\b[0-9]+", "$12 34"), Some((4, 6)));
+        assert_eq!(find("(?<=ab|c)d", "abd"), Some((2, 3)));
+        assert_eq!(find("(?<=ab|c)d", "bd cd"), Some((4, 5)));
+        assert_eq!(find("(?<=a{2})b", "ab aab"), Some((5, 6)));
+        // lookarounds at the edges of the input
+        assert_eq!(find("(?<!a)", ""), Some((0, 0)));
+        assert_eq!(find("(?<=a)", "a"), Some((1, 1)));
+        assert_eq!(find("a(?!.)", "aba"), Some((2, 3)));
+        // nested
+        assert_eq!(find("(?=a(?<=xa))a", "a xa"), Some((3, 4)));
+
+        assert_eq!(
+            build_nfa(parse(lex("(?<=a+)b").unwrap()).unwrap()).unwrap_err(),
+            "Lookbehind of unbounded length is not supported by the NFA engine; \
+             use the backtracking engine"
+        );
+    }
 }
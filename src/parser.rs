@@ -1,62 +1,311 @@
 use std::iter::Peekable;
 
+use crate::charset::{posix_class, CharSet};
 use crate::lexer::Token;
+use crate::unicode;
 
-#[derive(Debug, Eq, PartialEq)]
+/// A zero-width assertion on the characters around a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assertion {
+    WordBoundary,    // \b
+    NotWordBoundary, // \B
+    StartText,       // \A, or ^ without the m flag
+    EndText,         // \z, or $ without the m flag
+    // \Z, which also holds before a line terminator at the end. The bool of
+    // this and the line anchors is true if \r\n is a line terminator too.
+    EndTextOrNewline(bool),
+    StartLine(bool), // ^ with the m flag
+    EndLine(bool),   // $ with the m flag
+    // no word character before or after, which grep -w puts around a
+    // pattern; they have no syntax of their own
+    #[allow(dead_code)]
+    NotAfterWord,
+    #[allow(dead_code)]
+    NotBeforeWord,
+}
+
+impl Assertion {
+    /// Checks the assertion at `pos` in `chars`, which is between
+    /// `chars[pos - 1]` and `chars[pos]`.
+    pub fn holds(&self, chars: &[char], pos: usize) -> bool {
+        let prev = pos.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(pos).copied();
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        match *self {
+            Assertion::WordBoundary => is_word(prev) != is_word(next),
+            Assertion::NotWordBoundary => is_word(prev) == is_word(next),
+            Assertion::StartText => prev.is_none(),
+            Assertion::EndText => next.is_none(),
+            Assertion::EndTextOrNewline(crlf) => {
+                let rest = &chars[pos..];
+                rest.is_empty() || rest == ['\n'] || (crlf && rest == ['\r', '\n'])
+            }
+            // with \r\n, a \r alone also ends a line, but a position between
+            // \r and \n is neither the start nor the end of one
+            Assertion::StartLine(crlf) => match prev {
+                None | Some('\n') => true,
+                Some('\r') => crlf && next != Some('\n'),
+                _ => false,
+            },
+            Assertion::EndLine(crlf) => match next {
+                None => true,
+                Some('\n') => !crlf || prev != Some('\r'),
+                Some('\r') => crlf,
+                _ => false,
+            },
+            Assertion::NotAfterWord => !is_word(prev),
+            Assertion::NotBeforeWord => !is_word(next),
+        }
+    }
+}
+
+impl std::fmt::Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::WordBoundary => write!(f, "\\b"),
+            Assertion::NotWordBoundary => write!(f, "\\B"),
+            Assertion::StartText => write!(f, "\\A"),
+            Assertion::EndText => write!(f, "\\z"),
+            Assertion::EndTextOrNewline(_) => write!(f, "\\Z"),
+            Assertion::StartLine(_) => write!(f, "^"),
+            Assertion::EndLine(_) => write!(f, "$"),
+            Assertion::NotAfterWord => write!(f, "(?<!\\w)"),
+            Assertion::NotBeforeWord => write!(f, "(?!\\w)"),
+        }
+    }
+}
+
+/// The kind of a lookaround group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LookAround {
+    Ahead,          // (?=...)
+    NegativeAhead,  // (?!...)
+    Behind,         // (?<=...)
+    NegativeBehind, // (?<!...)
+}
+
+impl LookAround {
+    pub fn is_behind(&self) -> bool {
+        matches!(self, LookAround::Behind | LookAround::NegativeBehind)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        matches!(self, LookAround::NegativeAhead | LookAround::NegativeBehind)
+    }
+}
+
+impl std::fmt::Display for LookAround {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookAround::Ahead => write!(f, "(?="),
+            LookAround::NegativeAhead => write!(f, "(?!"),
+            LookAround::Behind => write!(f, "(?<="),
+            LookAround::NegativeBehind => write!(f, "(?<!"),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Node {
     Literal(char),
     AnyChar,
-    CharClass(Vec<char>),
-    Or(Box<Node>, Box<Node>),
+    CharClass(CharSet),
+    Empty,                  // matches the empty string, e.g. an empty branch in a|
+    Alternation(Vec<Node>), // a|b|c, with the branches in priority order
     ZeroOrMore(Box<Node>),
     OneOrMore(Box<Node>),
     ZeroOrOne(Box<Node>),
+    Repeat(Box<Node>, usize, Option<usize>), // {min,max}, max is None if unbounded
+    Lazy(Box<Node>),                         // wraps a quantifier to prefer fewer repetitions
+    Atomic(Box<Node>),                       // (?>...) and possessive quantifiers
+    Assertion(Assertion),
+    LookAround(LookAround, Box<Node>),
     Group(Box<Node>),
+    NamedGroup(String, Box<Node>),
+    Backreference(usize),
+    NamedBackreference(String),
     Concat(Vec<Node>),
 }
 
+impl Node {
+    /// Returns the direct children of the node.
+    #[allow(dead_code)]
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Literal(_)
+            | Node::AnyChar
+            | Node::CharClass(_)
+            | Node::Empty
+            | Node::Assertion(_)
+            | Node::Backreference(_)
+            | Node::NamedBackreference(_) => vec![],
+            Node::ZeroOrMore(node)
+            | Node::OneOrMore(node)
+            | Node::ZeroOrOne(node)
+            | Node::Repeat(node, _, _)
+            | Node::Lazy(node)
+            | Node::Atomic(node)
+            | Node::LookAround(_, node)
+            | Node::Group(node)
+            | Node::NamedGroup(_, node) => vec![node],
+            Node::Concat(nodes) | Node::Alternation(nodes) => nodes.iter().collect(),
+        }
+    }
+
+    /// Returns the direct children of the node, mutably.
+    #[allow(dead_code)]
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        match self {
+            Node::Literal(_)
+            | Node::AnyChar
+            | Node::CharClass(_)
+            | Node::Empty
+            | Node::Assertion(_)
+            | Node::Backreference(_)
+            | Node::NamedBackreference(_) => vec![],
+            Node::ZeroOrMore(node)
+            | Node::OneOrMore(node)
+            | Node::ZeroOrOne(node)
+            | Node::Repeat(node, _, _)
+            | Node::Lazy(node)
+            | Node::Atomic(node)
+            | Node::LookAround(_, node)
+            | Node::Group(node)
+            | Node::NamedGroup(_, node) => vec![node],
+            Node::Concat(nodes) | Node::Alternation(nodes) => nodes.iter_mut().collect(),
+        }
+    }
+
+    /// Returns true if the node or any of its descendants satisfies `predicate`.
+    #[allow(dead_code)]
+    pub fn any(&self, predicate: &impl Fn(&Node) -> bool) -> bool {
+        predicate(self) || self.children().into_iter().any(|node| node.any(predicate))
+    }
+
+    /// Returns the minimum and maximum number of characters the node can
+    /// match. The maximum is None if it is unbounded.
+    pub fn length_range(&self) -> (usize, Option<usize>) {
+        match self {
+            Node::Literal(_) | Node::AnyChar | Node::CharClass(_) => (1, Some(1)),
+            Node::Empty | Node::Assertion(_) | Node::LookAround(_, _) => (0, Some(0)),
+            Node::Group(node)
+            | Node::NamedGroup(_, node)
+            | Node::Atomic(node)
+            | Node::Lazy(node) => node.length_range(),
+            // the captured text can be of any length
+            Node::Backreference(_) | Node::NamedBackreference(_) => (0, None),
+            Node::Concat(nodes) => nodes.iter().fold((0, Some(0)), |(min, max), node| {
+                let (node_min, node_max) = node.length_range();
+                (min + node_min, max.zip(node_max).map(|(a, b)| a + b))
+            }),
+            Node::Alternation(nodes) => {
+                let ranges = nodes.iter().map(|node| node.length_range());
+                let min = ranges.clone().map(|(min, _)| min).min().unwrap_or(0);
+                let max = ranges.map(|(_, max)| max).try_fold(0, |max, node_max| {
+                    node_max.map(|node_max| max.max(node_max))
+                });
+                (min, max)
+            }
+            Node::ZeroOrMore(node) => (0, unbounded_unless_empty(node)),
+            Node::OneOrMore(node) => (node.length_range().0, unbounded_unless_empty(node)),
+            Node::ZeroOrOne(node) => (0, node.length_range().1),
+            Node::Repeat(node, min, max) => {
+                let (node_min, node_max) = node.length_range();
+                let max = match (node_max, max) {
+                    (Some(0), _) => Some(0),
+                    (Some(node_max), Some(max)) => Some(node_max * max),
+                    _ => None,
+                };
+                (node_min * min, max)
+            }
+        }
+    }
+}
+
+fn unbounded_unless_empty(node: &Node) -> Option<usize> {
+    match node.length_range().1 {
+        Some(0) => Some(0),
+        _ => None,
+    }
+}
+
 pub fn parse(tokens: Vec<Token>) -> Result<Node, String> {
     let mut tokens = tokens.into_iter().peekable();
-    parse_expr(&mut tokens)
+    let node = parse_expr(&mut tokens)?;
+    if tokens.next().is_some() {
+        return Err("Unmatched closing parenthesis".to_string());
+    }
+    Ok(node)
 }
 
+// parses branches separated by `|`. An empty branch matches the empty
+// string, so that a| is the same as a?, and |a the same as a??.
 fn parse_expr(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node, String> {
-    let mut left = parse_term(tokens)?;
+    let mut branches = vec![parse_term(tokens)?];
 
     while let Some(token) = tokens.peek() {
         match token {
             Token::Pipe => {
                 tokens.next();
-                let right = parse_term(tokens)?;
-                left = Node::Or(Box::new(left), Box::new(right));
+                branches.push(parse_term(tokens)?);
             }
             _ => break,
         }
     }
 
-    Ok(left)
+    if branches.len() == 1 {
+        Ok(branches.pop().unwrap())
+    } else {
+        Ok(Node::Alternation(branches))
+    }
 }
 
 fn parse_term(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node, String> {
     let mut nodes = Vec::new();
     while let Some(token) = tokens.peek() {
         match token {
-            Token::Literal(_) | Token::Dot | Token::LeftParen | Token::LeftBracket => {
+            Token::Literal(_)
+            | Token::Dot
+            | Token::LeftParen
+            | Token::AtomicGroup
+            | Token::LookAhead
+            | Token::NegativeLookAhead
+            | Token::LookBehind
+            | Token::NegativeLookBehind
+            | Token::NamedGroup(_)
+            | Token::LeftBracket
+            | Token::Property(_, _)
+            | Token::WordBoundary
+            | Token::NotWordBoundary
+            | Token::StartText
+            | Token::EndText
+            | Token::EndTextOrNewline(_)
+            | Token::StartLine(_)
+            | Token::EndLine(_)
+            | Token::Backreference(_)
+            | Token::NamedBackreference(_) => {
                 nodes.push(parse_factor(tokens)?);
             }
             Token::Pipe | Token::RightParen => {
                 break;
             }
+            // e.g. *a or (+a)
+            Token::Star | Token::Plus | Token::Question | Token::Repeat(_, _) => {
+                return Err(format!(
+                    "Quantifier {} has nothing to repeat",
+                    quantifier_text(token)
+                ));
+            }
             _ => {
                 return Err(format!("Unexpected token: {:?}", token));
             }
         }
     }
 
-    if nodes.len() == 1 {
-        Ok(nodes.pop().unwrap())
-    } else {
-        Ok(Node::Concat(nodes))
+    match nodes.len() {
+        0 => Ok(Node::Empty),
+        1 => Ok(nodes.pop().unwrap()),
+        _ => Ok(Node::Concat(nodes)),
     }
 }
 
@@ -65,14 +314,35 @@ fn parse_factor(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<No
     let node = match token {
         Token::Literal(c) => Ok(Node::Literal(c)),
         Token::Dot => Ok(Node::AnyChar),
-        Token::LeftParen => {
-            let expr = parse_expr(tokens)?;
-            if let Some(Token::RightParen) = tokens.next() {
-                Ok(Node::Group(Box::new(expr)))
-            } else {
-                Err(format!("Unclosed group"))
-            }
-        }
+        Token::WordBoundary => Ok(Node::Assertion(Assertion::WordBoundary)),
+        Token::NotWordBoundary => Ok(Node::Assertion(Assertion::NotWordBoundary)),
+        Token::StartText => Ok(Node::Assertion(Assertion::StartText)),
+        Token::EndText => Ok(Node::Assertion(Assertion::EndText)),
+        Token::EndTextOrNewline(crlf) => Ok(Node::Assertion(Assertion::EndTextOrNewline(crlf))),
+        Token::StartLine(crlf) => Ok(Node::Assertion(Assertion::StartLine(crlf))),
+        Token::EndLine(crlf) => Ok(Node::Assertion(Assertion::EndLine(crlf))),
+        Token::LeftParen => Ok(Node::Group(Box::new(parse_group(tokens)?))),
+        Token::AtomicGroup => Ok(Node::Atomic(Box::new(parse_group(tokens)?))),
+        Token::LookAhead => Ok(Node::LookAround(
+            LookAround::Ahead,
+            Box::new(parse_group(tokens)?),
+        )),
+        Token::NegativeLookAhead => Ok(Node::LookAround(
+            LookAround::NegativeAhead,
+            Box::new(parse_group(tokens)?),
+        )),
+        Token::LookBehind => Ok(Node::LookAround(
+            LookAround::Behind,
+            Box::new(parse_group(tokens)?),
+        )),
+        Token::NegativeLookBehind => Ok(Node::LookAround(
+            LookAround::NegativeBehind,
+            Box::new(parse_group(tokens)?),
+        )),
+        Token::NamedGroup(name) => Ok(Node::NamedGroup(name, Box::new(parse_group(tokens)?))),
+        Token::Property(name, negated) => Ok(Node::CharClass(property(&name, negated)?)),
+        Token::Backreference(group) => Ok(Node::Backreference(group)),
+        Token::NamedBackreference(name) => Ok(Node::NamedBackreference(name)),
         Token::LeftBracket => {
             let expr = parse_char_class(tokens)?;
             Ok(expr)
@@ -80,73 +350,192 @@ fn parse_factor(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<No
         _ => Err(format!("Unexpected token: {:?}", token)),
     }?;
 
-    if let Some(_) = tokens.peek() {
+    if tokens.peek().is_some() {
         parse_repetition(tokens, node)
     } else {
         Ok(node)
     }
 }
 
+// parses the inside of a group up to its closing parenthesis
+fn parse_group(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node, String> {
+    let expr = parse_expr(tokens)?;
+    if let Some(Token::RightParen) = tokens.next() {
+        Ok(expr)
+    } else {
+        Err("Unclosed group".to_string())
+    }
+}
+
+// upper bound for counted repetition, which is expanded when building the NFA
+const MAX_REPEAT: usize = 1000;
+
 fn parse_repetition(
     tokens: &mut Peekable<impl Iterator<Item = Token>>,
     node: Node,
 ) -> Result<Node, String> {
-    let token = tokens.peek().unwrap();
-    match token {
-        Token::Star => {
-            tokens.next();
-            Ok(Node::ZeroOrMore(Box::new(node)))
+    let token = tokens.peek().unwrap().clone();
+    let node = match token {
+        Token::Star => Node::ZeroOrMore(Box::new(node)),
+        Token::Plus => Node::OneOrMore(Box::new(node)),
+        Token::Question => Node::ZeroOrOne(Box::new(node)),
+        Token::Repeat(min, max) => {
+            if max.is_some_and(|max| min > max) {
+                return Err(format!(
+                    "Invalid repetition range: {{{},{}}}",
+                    min,
+                    max.unwrap()
+                ));
+            }
+            if max.unwrap_or(min) > MAX_REPEAT {
+                return Err(format!("Repetition count exceeds {}", MAX_REPEAT));
+            }
+            Node::Repeat(Box::new(node), min, max)
         }
-        Token::Plus => {
+        _ => return Ok(node),
+    };
+    tokens.next();
+
+    let node = match tokens.peek() {
+        // e.g. a*?
+        Some(Token::Question) => {
             tokens.next();
-            Ok(Node::OneOrMore(Box::new(node)))
+            Node::Lazy(Box::new(node))
         }
-        Token::Question => {
+        // e.g. a*+, which never gives back what it matched
+        Some(Token::Plus) => {
             tokens.next();
-            Ok(Node::ZeroOrOne(Box::new(node)))
+            Node::Atomic(Box::new(node))
+        }
+        _ => node,
+    };
+
+    // a quantifier takes at most one modifier, and quantifying it again
+    // needs a group, e.g. a** or a*?? is an error but (a*)* is fine
+    match tokens.peek() {
+        Some(token @ (Token::Star | Token::Plus | Token::Question | Token::Repeat(_, _))) => {
+            Err(format!(
+                "Quantifier {} follows another quantifier; use a group to repeat it",
+                quantifier_text(token)
+            ))
         }
         _ => Ok(node),
     }
 }
 
+// the quantifier as written in the pattern, for error messages
+fn quantifier_text(token: &Token) -> String {
+    match token {
+        Token::Star => "*".to_string(),
+        Token::Plus => "+".to_string(),
+        Token::Question => "?".to_string(),
+        Token::Repeat(min, Some(max)) if min == max => format!("{{{}}}", min),
+        Token::Repeat(min, Some(max)) => format!("{{{},{}}}", min, max),
+        Token::Repeat(min, None) => format!("{{{},}}", min),
+        _ => format!("{:?}", token),
+    }
+}
+
 fn parse_char_class(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<Node, String> {
-    let mut chars = Vec::new();
-    while tokens.peek().is_some() {
+    Ok(Node::CharClass(parse_class_set(tokens)?))
+}
+
+// parses the inside of a class up to and including its `]`. The set operators
+// all have the same precedence and apply from left to right, e.g.
+// [a-z--aeiou&&a-m] is [[[a-z]--[aeiou]]&&[a-m]].
+fn parse_class_set(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Result<CharSet, String> {
+    let mut set = parse_class_union(tokens)?;
+    loop {
+        let operator = match tokens.next() {
+            Some(Token::RightBracket) => return Ok(set),
+            None => return Err("Unclosed character class".to_string()),
+            Some(token) => token,
+        };
+        let operand = parse_class_union(tokens)?;
+        set = match operator {
+            Token::Intersection => set.intersection(&operand),
+            Token::Difference => set.difference(&operand),
+            Token::SymmetricDifference => set.symmetric_difference(&operand),
+            _ => unreachable!(),
+        };
+    }
+}
+
+// parses the members of a class up to the next set operator or `]`
+fn parse_class_union(
+    tokens: &mut Peekable<impl Iterator<Item = Token>>,
+) -> Result<CharSet, String> {
+    let mut set = CharSet::new();
+    // the last literal, which may still start a range
+    let mut last: Option<char> = None;
+    while let Some(token) = tokens.peek() {
+        if matches!(
+            token,
+            Token::RightBracket
+                | Token::Intersection
+                | Token::Difference
+                | Token::SymmetricDifference
+        ) {
+            break;
+        }
         let token = tokens.next().unwrap();
         match token {
-            Token::Literal(c) => chars.push(c),
-            Token::Hyphen => {
-                if chars.is_empty() {
-                    return Err(format!("Hyphen at the beginning of char class"));
+            Token::Literal(c) => {
+                if let Some(last) = last {
+                    set.add(last);
                 }
-                let next_token = tokens.next();
-                if next_token.is_some() {
-                    if let Some(Token::Literal(char)) = next_token {
-                        let first = chars.pop().unwrap();
-                        let last = char;
-                        if first > last {
-                            return Err(format!("invalid char class"));
-                        }
-                        for c in first..=last {
-                            chars.push(c);
-                        }
+                last = Some(c);
+            }
+            Token::Hyphen => match (last.take(), tokens.peek()) {
+                // a range, e.g. [a-z]
+                (Some(first), Some(&Token::Literal(end))) => {
+                    tokens.next();
+                    if first > end {
+                        return Err(format!("Invalid range in char class: {}-{}", first, end));
                     }
-
-                    // e.g. [a-z-]
-                    if let Some(Token::Hyphen) = tokens.peek() {
-                        return Err(format!("invalid char class"));
+                    set.add_range(first, end);
+                }
+                // e.g. [a-\p{L}]
+                (
+                    Some(first),
+                    Some(Token::LeftBracket | Token::PosixClass(_, _) | Token::Property(_, _)),
+                ) => {
+                    return Err(format!(
+                        "Range in char class must end with a character: {}-",
+                        first
+                    ));
+                }
+                // a literal at the start or the end of a class, or after a
+                // range, e.g. [-a], [a-] or [a-z-0]
+                (first, _) => {
+                    if let Some(first) = first {
+                        set.add(first);
                     }
-                } else {
-                    return Err(format!("invalid char class"));
+                    set.add('-');
                 }
+            },
+            Token::PosixClass(name, negated) => {
+                let class = posix_class(&name)
+                    .ok_or_else(|| format!("Unknown POSIX class: [:{}:]", name))?;
+                set.union(&if negated { class.negate() } else { class });
             }
-            Token::RightBracket => {
-                break;
-            }
+            Token::Property(name, negated) => set.union(&property(&name, negated)?),
+            // a nested class
+            Token::LeftBracket => set.union(&parse_class_set(tokens)?),
             _ => return Err(format!("Unexpected token: {:?}", token)),
         }
     }
-    Ok(Node::CharClass(chars))
+    if let Some(last) = last {
+        set.add(last);
+    }
+    Ok(set)
+}
+
+// the set for \p{name}, or for \P{name} if negated
+fn property(name: &str, negated: bool) -> Result<CharSet, String> {
+    let set =
+        unicode::property(name).ok_or_else(|| format!("Unknown Unicode property: {}", name))?;
+    Ok(if negated { set.negate() } else { set })
 }
 
 #[cfg(test)]
@@ -187,45 +576,43 @@ mod tests {
         );
         assert_eq!(
             parse(lex("a|b").unwrap()),
-            Ok(Node::Or(
-                Box::new(Node::Literal('a')),
-                Box::new(Node::Literal('b'))
-            ))
+            Ok(Node::Alternation(vec![
+                Node::Literal('a'),
+                Node::Literal('b')
+            ]))
         );
         assert_eq!(
             parse(lex("a|b|c").unwrap()),
-            Ok(Node::Or(
-                Box::new(Node::Or(
-                    Box::new(Node::Literal('a')),
-                    Box::new(Node::Literal('b'))
-                )),
-                Box::new(Node::Literal('c'))
-            ))
+            Ok(Node::Alternation(vec![
+                Node::Literal('a'),
+                Node::Literal('b'),
+                Node::Literal('c')
+            ]))
         );
         assert_eq!(
             parse(lex("a*|b").unwrap()),
-            Ok(Node::Or(
-                Box::new(Node::ZeroOrMore(Box::new(Node::Literal('a')))),
-                Box::new(Node::Literal('b'))
-            ))
+            Ok(Node::Alternation(vec![
+                Node::ZeroOrMore(Box::new(Node::Literal('a'))),
+                Node::Literal('b')
+            ]))
         );
         assert_eq!(
             parse(lex("a|([a-c])").unwrap()),
-            Ok(Node::Or(
-                Box::new(Node::Literal('a')),
-                Box::new(Node::Group(Box::new(Node::CharClass(vec!['a', 'b', 'c']))))
-            ))
+            Ok(Node::Alternation(vec![
+                Node::Literal('a'),
+                Node::Group(Box::new(Node::CharClass(vec!['a', 'b', 'c'].into())))
+            ]))
         );
         assert_eq!(
             parse(lex("abc|d").unwrap()),
-            Ok(Node::Or(
-                Box::new(Node::Concat(vec![
+            Ok(Node::Alternation(vec![
+                Node::Concat(vec![
                     Node::Literal('a'),
                     Node::Literal('b'),
                     Node::Literal('c')
-                ])),
-                Box::new(Node::Literal('d'))
-            ))
+                ]),
+                Node::Literal('d')
+            ]))
         );
         assert_eq!(
             parse(lex("(ab)*").unwrap()),
@@ -235,27 +622,502 @@ mod tests {
         );
         assert_eq!(
             parse(lex("[abc]").unwrap()),
-            Ok(Node::CharClass(vec!['a', 'b', 'c']))
+            Ok(Node::CharClass(vec!['a', 'b', 'c'].into()))
         );
         assert_eq!(
             parse(lex("[abc]*").unwrap()),
-            Ok(Node::ZeroOrMore(Box::new(Node::CharClass(vec![
-                'a', 'b', 'c'
-            ]))))
+            Ok(Node::ZeroOrMore(Box::new(Node::CharClass(
+                vec!['a', 'b', 'c'].into()
+            ))))
         );
         assert_eq!(
             parse(lex("[abc]+").unwrap()),
-            Ok(Node::OneOrMore(Box::new(Node::CharClass(vec![
-                'a', 'b', 'c'
-            ]))))
+            Ok(Node::OneOrMore(Box::new(Node::CharClass(
+                vec!['a', 'b', 'c'].into()
+            ))))
         );
         assert_eq!(
             parse(lex("([a-c])").unwrap()),
-            Ok(Node::Group(Box::new(Node::CharClass(vec!['a', 'b', 'c']))))
+            Ok(Node::Group(Box::new(Node::CharClass(
+                vec!['a', 'b', 'c'].into()
+            ))))
         );
         assert_eq!(
             parse(lex("[(a-c)]").unwrap()),
-            Err("Unexpected token: LeftParen".to_string())
+            Ok(Node::CharClass(vec!['(', 'a', 'b', 'c', ')'].into()))
+        );
+        assert_eq!(
+            parse(lex("[.*+?|]").unwrap()),
+            Ok(Node::CharClass(vec!['.', '*', '+', '?', '|'].into()))
+        );
+        assert_eq!(
+            parse(lex("[(?<a>)]").unwrap()),
+            Ok(Node::CharClass(vec!['(', '?', '<', 'a', '>', ')'].into()))
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(parse(lex("").unwrap()), Ok(Node::Empty));
+        assert_eq!(
+            parse(lex("()").unwrap()),
+            Ok(Node::Group(Box::new(Node::Empty)))
+        );
+        assert_eq!(
+            parse(lex("a|").unwrap()),
+            Ok(Node::Alternation(vec![Node::Literal('a'), Node::Empty]))
+        );
+        assert_eq!(
+            parse(lex("|a").unwrap()),
+            Ok(Node::Alternation(vec![Node::Empty, Node::Literal('a')]))
+        );
+        assert_eq!(
+            parse(lex("a||b").unwrap()),
+            Ok(Node::Alternation(vec![
+                Node::Literal('a'),
+                Node::Empty,
+                Node::Literal('b')
+            ]))
+        );
+        assert_eq!(
+            parse(lex("(|)").unwrap()),
+            Ok(Node::Group(Box::new(Node::Alternation(vec![
+                Node::Empty,
+                Node::Empty
+            ]))))
+        );
+        assert_eq!(
+            parse(lex("a)").unwrap()),
+            Err("Unmatched closing parenthesis".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_posix_class() {
+        assert_eq!(
+            parse(lex("[[:digit:]x]").unwrap()),
+            Ok(Node::CharClass(CharSet::from_ranges([
+                ('0', '9'),
+                ('x', 'x')
+            ])))
+        );
+        assert_eq!(
+            parse(lex("[[:xdigit:][:lower:]]").unwrap()),
+            Ok(Node::CharClass(CharSet::from_ranges([
+                ('0', '9'),
+                ('A', 'F'),
+                ('a', 'z')
+            ])))
+        );
+        let Ok(Node::CharClass(set)) = parse(lex("[[:^alpha:]]").unwrap()) else {
+            panic!("not a char class");
+        };
+        assert!(set.contains('1'));
+        assert!(set.contains('é'));
+        assert!(!set.contains('a'));
+        assert_eq!(
+            parse(lex("[[:alhpa:]]").unwrap()),
+            Err("Unknown POSIX class: [:alhpa:]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_property() {
+        let Ok(Node::CharClass(greek)) = parse(lex("\\p{Greek}").unwrap()) else {
+            panic!("not a char class");
+        };
+        assert!(greek.contains('λ'));
+        assert!(!greek.contains('l'));
+        let Ok(Node::CharClass(set)) = parse(lex("\\P{Greek}").unwrap()) else {
+            panic!("not a char class");
+        };
+        assert!(!set.contains('λ'));
+        assert!(set.contains('l'));
+        let Ok(Node::CharClass(set)) = parse(lex("[\\p{Nd}\\p{Greek}_]").unwrap()) else {
+            panic!("not a char class");
+        };
+        assert!(set.contains('λ'));
+        assert!(set.contains('7'));
+        assert!(set.contains('_'));
+        assert!(!set.contains('a'));
+        assert_eq!(
+            parse(lex("\\p{Greekish}").unwrap()),
+            Err("Unknown Unicode property: Greekish".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_class_set_operation() {
+        let class = |pattern: &str| match parse(lex(pattern).unwrap()) {
+            Ok(Node::CharClass(set)) => set,
+            other => panic!("not a char class: {:?}", other),
+        };
+        assert_eq!(class("[[a-c]x]"), CharSet::from(vec!['a', 'b', 'c', 'x']));
+        assert_eq!(class("[a-z&&[c-f]]"), CharSet::from_ranges([('c', 'f')]));
+        assert_eq!(
+            class("[a-f--[aeiou]]"),
+            CharSet::from(vec!['b', 'c', 'd', 'f'])
         );
+        assert_eq!(
+            class("[a-f~~d-h]"),
+            CharSet::from_ranges([('a', 'c'), ('g', 'h')])
+        );
+        // left to right, after the unions
+        assert_eq!(
+            class("[a-z--aeiou&&a-f]"),
+            CharSet::from(vec!['b', 'c', 'd', 'f'])
+        );
+        assert_eq!(class("[ab&&bc]"), CharSet::from(vec!['b']));
+        assert_eq!(class("[[ab]&&[cd]]"), CharSet::new());
+        assert_eq!(
+            class("[[a-c[x-z]]--[b[y]]]"),
+            CharSet::from(vec!['a', 'c', 'x', 'z'])
+        );
+
+        let greek_upper = class("[\\p{Greek}&&\\p{Lu}]");
+        assert!(greek_upper.contains('Ω'));
+        assert!(!greek_upper.contains('ω'));
+        assert!(!greek_upper.contains('A'));
+        assert_eq!(
+            class("[[:alpha:]--[:upper:]]"),
+            CharSet::from_ranges([('a', 'z')])
+        );
+
+        for pattern in ["[abc", "[a[b]", "[a&&b", "[]", "[^", "[a-"] {
+            assert_eq!(
+                parse(lex(pattern).unwrap()),
+                Err("Unclosed character class".to_string()),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_class_hyphen() {
+        let class = |pattern: &str| match parse(lex(pattern).unwrap()) {
+            Ok(Node::CharClass(set)) => set,
+            other => panic!("not a char class: {:?}", other),
+        };
+        assert_eq!(class("[-a]"), CharSet::from(vec!['-', 'a']));
+        assert_eq!(class("[a-]"), CharSet::from(vec!['-', 'a']));
+        assert_eq!(class("[-]"), CharSet::from(vec!['-']));
+        assert_eq!(
+            class("[a-c-e]"),
+            CharSet::from(vec!['a', 'b', 'c', '-', 'e'])
+        );
+        assert_eq!(
+            class("[a-z-]"),
+            CharSet::from_ranges([('a', 'z'), ('-', '-')])
+        );
+        assert_eq!(
+            class("[0-9-a-c]"),
+            CharSet::from_ranges([('0', '9'), ('-', '-'), ('a', 'c')])
+        );
+        assert_eq!(
+            class("[[:digit:]-x]"),
+            CharSet::from_ranges([('0', '9'), ('-', '-'), ('x', 'x')])
+        );
+        assert_eq!(class("[\\--/]"), CharSet::from_ranges([('-', '/')]));
+        assert_eq!(
+            parse(lex("[z-a]").unwrap()),
+            Err("Invalid range in char class: z-a".to_string())
+        );
+        assert_eq!(
+            parse(lex("[a-[:digit:]]").unwrap()),
+            Err("Range in char class must end with a character: a-".to_string())
+        );
+
+        // `]` is a literal as the first member
+        assert_eq!(class("[]a]"), CharSet::from(vec![']', 'a']));
+        assert_eq!(class("[]-a]"), CharSet::from_ranges([(']', 'a')]));
+        assert_eq!(class("[a[]]]"), CharSet::from(vec!['a', ']']));
+        assert_eq!(
+            parse(lex("[]a]+").unwrap()),
+            Ok(Node::OneOrMore(Box::new(Node::CharClass(
+                vec![']', 'a'].into()
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_parse_repetition() {
+        assert_eq!(
+            parse(lex("a{2,3}").unwrap()),
+            Ok(Node::Repeat(Box::new(Node::Literal('a')), 2, Some(3)))
+        );
+        assert_eq!(
+            parse(lex("a{2,}b").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Repeat(Box::new(Node::Literal('a')), 2, None),
+                Node::Literal('b')
+            ]))
+        );
+        assert_eq!(
+            parse(lex("a{3,2}").unwrap()),
+            Err("Invalid repetition range: {3,2}".to_string())
+        );
+        assert_eq!(
+            parse(lex("a{1001}").unwrap()),
+            Err("Repetition count exceeds 1000".to_string())
+        );
+
+        // lazy
+        assert_eq!(
+            parse(lex("a*?").unwrap()),
+            Ok(Node::Lazy(Box::new(Node::ZeroOrMore(Box::new(
+                Node::Literal('a')
+            )))))
+        );
+        assert_eq!(
+            parse(lex("a+?").unwrap()),
+            Ok(Node::Lazy(Box::new(Node::OneOrMore(Box::new(
+                Node::Literal('a')
+            )))))
+        );
+        assert_eq!(
+            parse(lex("a??").unwrap()),
+            Ok(Node::Lazy(Box::new(Node::ZeroOrOne(Box::new(
+                Node::Literal('a')
+            )))))
+        );
+        assert_eq!(
+            parse(lex("a{1,2}?b").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Lazy(Box::new(Node::Repeat(
+                    Box::new(Node::Literal('a')),
+                    1,
+                    Some(2)
+                ))),
+                Node::Literal('b')
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_stacked_quantifier() {
+        assert_eq!(
+            parse(lex("a**").unwrap()),
+            Err("Quantifier * follows another quantifier; use a group to repeat it".to_string())
+        );
+        assert_eq!(
+            parse(lex("a+{2}").unwrap()),
+            Err("Quantifier {2} follows another quantifier; use a group to repeat it".to_string())
+        );
+        assert_eq!(
+            parse(lex("a*??").unwrap()),
+            Err("Quantifier ? follows another quantifier; use a group to repeat it".to_string())
+        );
+        assert_eq!(
+            parse(lex("a{1,2}++").unwrap()),
+            Err("Quantifier + follows another quantifier; use a group to repeat it".to_string())
+        );
+        assert_eq!(
+            parse(lex("*a").unwrap()),
+            Err("Quantifier * has nothing to repeat".to_string())
+        );
+        assert_eq!(
+            parse(lex("a|+").unwrap()),
+            Err("Quantifier + has nothing to repeat".to_string())
+        );
+        assert_eq!(
+            parse(lex("(|?)").unwrap()),
+            Err("Quantifier ? has nothing to repeat".to_string())
+        );
+        assert_eq!(
+            parse(lex("({2,})").unwrap()),
+            Err("Quantifier {2,} has nothing to repeat".to_string())
+        );
+
+        // a group can be quantified again, even if it can match empty
+        assert_eq!(
+            parse(lex("(a*)*").unwrap()),
+            Ok(Node::ZeroOrMore(Box::new(Node::Group(Box::new(
+                Node::ZeroOrMore(Box::new(Node::Literal('a')))
+            )))))
+        );
+        assert_eq!(
+            parse(lex("()+").unwrap()),
+            Ok(Node::OneOrMore(Box::new(Node::Group(Box::new(
+                Node::Empty
+            )))))
+        );
+        // and so can assertions
+        assert_eq!(
+            parse(lex("^*a").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::ZeroOrMore(Box::new(Node::Assertion(Assertion::StartText))),
+                Node::Literal('a')
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_atomic() {
+        assert_eq!(
+            parse(lex("(?>ab)").unwrap()),
+            Ok(Node::Atomic(Box::new(Node::Concat(vec![
+                Node::Literal('a'),
+                Node::Literal('b')
+            ]))))
+        );
+        assert_eq!(
+            parse(lex("(?>a").unwrap()),
+            Err("Unclosed group".to_string())
+        );
+        assert_eq!(
+            parse(lex("a*+").unwrap()),
+            Ok(Node::Atomic(Box::new(Node::ZeroOrMore(Box::new(
+                Node::Literal('a')
+            )))))
+        );
+        assert_eq!(
+            parse(lex("a++b").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Atomic(Box::new(Node::OneOrMore(Box::new(Node::Literal('a'))))),
+                Node::Literal('b')
+            ]))
+        );
+        assert_eq!(
+            parse(lex("a?+").unwrap()),
+            Ok(Node::Atomic(Box::new(Node::ZeroOrOne(Box::new(
+                Node::Literal('a')
+            )))))
+        );
+        assert_eq!(
+            parse(lex("a{1,2}+").unwrap()),
+            Ok(Node::Atomic(Box::new(Node::Repeat(
+                Box::new(Node::Literal('a')),
+                1,
+                Some(2)
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_parse_assertion() {
+        assert_eq!(
+            parse(lex("\\bid\\B").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Assertion(Assertion::WordBoundary),
+                Node::Literal('i'),
+                Node::Literal('d'),
+                Node::Assertion(Assertion::NotWordBoundary)
+            ]))
+        );
+
+        // checks the assertion between `prev` and `next`
+        let holds = |assertion: Assertion, prev: Option<char>, next: Option<char>| {
+            let chars: Vec<char> = prev.into_iter().chain(next).collect();
+            assertion.holds(&chars, prev.map_or(0, |_| 1))
+        };
+        let boundary = Assertion::WordBoundary;
+        assert!(holds(boundary, None, Some('a')));
+        assert!(holds(boundary, Some('a'), None));
+        assert!(holds(boundary, Some('a'), Some(' ')));
+        assert!(holds(boundary, Some('-'), Some('é')));
+        assert!(!holds(boundary, Some('a'), Some('_')));
+        assert!(!holds(boundary, Some(' '), Some('-')));
+        assert!(!holds(boundary, None, None));
+        let not_boundary = Assertion::NotWordBoundary;
+        assert!(holds(not_boundary, Some('a'), Some('1')));
+        assert!(holds(not_boundary, None, Some(' ')));
+        assert!(!holds(not_boundary, Some('a'), None));
+        assert!(holds(Assertion::NotAfterWord, None, Some('a')));
+        assert!(holds(Assertion::NotAfterWord, Some('-'), Some('@')));
+        assert!(!holds(Assertion::NotAfterWord, Some('a'), Some('@')));
+        assert!(holds(Assertion::NotBeforeWord, Some('a'), None));
+        assert!(!holds(Assertion::NotBeforeWord, Some('-'), Some('é')));
+
+        let chars: Vec<char> = "a\nb\r\nc\rd\n".chars().collect();
+        let positions = |assertion: Assertion| -> Vec<usize> {
+            (0..=chars.len())
+                .filter(|&pos| assertion.holds(&chars, pos))
+                .collect()
+        };
+        assert_eq!(positions(Assertion::StartText), vec![0]);
+        assert_eq!(positions(Assertion::EndText), vec![9]);
+        assert_eq!(positions(Assertion::EndTextOrNewline(false)), vec![8, 9]);
+        assert_eq!(positions(Assertion::StartLine(false)), vec![0, 2, 5, 9]);
+        assert_eq!(positions(Assertion::EndLine(false)), vec![1, 4, 8, 9]);
+        assert_eq!(positions(Assertion::StartLine(true)), vec![0, 2, 5, 7, 9]);
+        assert_eq!(positions(Assertion::EndLine(true)), vec![1, 3, 6, 8, 9]);
+        let chars: Vec<char> = "a\r\n".chars().collect();
+        assert!(!Assertion::EndTextOrNewline(false).holds(&chars, 1));
+        assert!(Assertion::EndTextOrNewline(true).holds(&chars, 1));
+        assert!(Assertion::EndTextOrNewline(true).holds(&chars, 2));
+    }
+
+    #[test]
+    fn test_parse_look_around() {
+        assert_eq!(
+            parse(lex("foo(?=bar)").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Literal('f'),
+                Node::Literal('o'),
+                Node::Literal('o'),
+                Node::LookAround(
+                    LookAround::Ahead,
+                    Box::new(Node::Concat(vec![
+                        Node::Literal('b'),
+                        Node::Literal('a'),
+                        Node::Literal('r')
+                    ]))
+                )
+            ]))
+        );
+        assert_eq!(
+            parse(lex("(?<!a|bc)").unwrap()),
+            Ok(Node::LookAround(
+                LookAround::NegativeBehind,
+                Box::new(Node::Alternation(vec![
+                    Node::Literal('a'),
+                    Node::Concat(vec![Node::Literal('b'), Node::Literal('c')])
+                ]))
+            ))
+        );
+        assert_eq!(
+            parse(lex("(?=a").unwrap()),
+            Err("Unclosed group".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_backreference() {
+        assert_eq!(
+            parse(lex("(a)\\1").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::Group(Box::new(Node::Literal('a'))),
+                Node::Backreference(1)
+            ]))
+        );
+        assert_eq!(
+            parse(lex("(?<q>a)\\k<q>+").unwrap()),
+            Ok(Node::Concat(vec![
+                Node::NamedGroup("q".to_string(), Box::new(Node::Literal('a'))),
+                Node::OneOrMore(Box::new(Node::NamedBackreference("q".to_string())))
+            ]))
+        );
+        let node = parse(lex("a(b|(c)\\2)").unwrap()).unwrap();
+        assert!(node.any(&|node| matches!(node, Node::Backreference(_))));
+        assert!(!node.any(&|node| matches!(node, Node::NamedGroup(_, _))));
+    }
+
+    #[test]
+    fn test_length_range() {
+        let range = |pattern: &str| parse(lex(pattern).unwrap()).unwrap().length_range();
+        assert_eq!(range("a"), (1, Some(1)));
+        assert_eq!(range("abc"), (3, Some(3)));
+        assert_eq!(range("a|bc"), (1, Some(2)));
+        assert_eq!(range("a|bc|d*"), (0, None));
+        assert_eq!(range("ab|"), (0, Some(2)));
+        assert_eq!(range(""), (0, Some(0)));
+        assert_eq!(range("a*"), (0, None));
+        assert_eq!(range("a+b?"), (1, None));
+        assert_eq!(range("(ab)?"), (0, Some(2)));
+        assert_eq!(range("a{2,3}"), (2, Some(3)));
+        assert_eq!(range("(ab){2,}"), (4, None));
+        assert_eq!(range("\\b(?=abc)"), (0, Some(0)));
+        assert_eq!(range("(\\b)*"), (0, Some(0)));
     }
 }
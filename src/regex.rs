@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+
+use crate::backtrack::{self, find_backtrack, find_backtrack_at};
+use crate::charset::CharSet;
+use crate::lexer::lex;
+use crate::nfa::{build_nfa, find_nfa, find_nfa_at, NFA};
+use crate::parser::{parse, Assertion, Node};
+
+/// The matching engine a `Regex` runs on.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Simulates the NFA. Runs in time linear to the input.
+    Nfa,
+    /// Backtracks over the pattern. Supports atomic groups, possessive
+    /// quantifiers, unbounded lookbehind and backreferences. Memoized, except
+    /// with backreferences and atomic groups, where a search gives up after a
+    /// step limit.
+    Backtrack,
+}
+
+impl Engine {
+    /// Returns the engine for `node`: the backtracking engine only if it
+    /// needs a feature the NFA lacks, i.e. backreferences, atomic groups
+    /// (also possessive quantifiers) or lookbehind of unbounded length.
+    pub fn for_node(node: &Node) -> Engine {
+        let needs_backtrack = |node: &Node| match node {
+            Node::Backreference(_) | Node::NamedBackreference(_) | Node::Atomic(_) => true,
+            Node::LookAround(kind, node) => kind.is_behind() && node.length_range().1.is_none(),
+            _ => false,
+        };
+        if node.any(&needs_backtrack) {
+            Engine::Backtrack
+        } else {
+            Engine::Nfa
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Program {
+    Nfa(NFA),
+    Backtrack(backtrack::Program),
+}
+
+#[derive(Debug)]
+pub struct Regex {
+    program: Program,
+}
+
+/// A match as a byte range of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Regex {
+    /// Compiles `pattern`, using the backtracking engine only if the NFA
+    /// cannot run it (see `Engine::for_node`).
+    #[allow(dead_code)]
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let node = parse(lex(pattern)?)?;
+        let engine = Engine::for_node(&node);
+        Self::from_node(node, engine)
+    }
+
+    #[allow(dead_code)]
+    pub fn with_engine(pattern: &str, engine: Engine) -> Result<Self, String> {
+        let node = parse(lex(pattern)?)?;
+        Self::from_node(node, engine)
+    }
+
+    pub fn from_node(node: Node, engine: Engine) -> Result<Self, String> {
+        let program = match engine {
+            Engine::Nfa => Program::Nfa(build_nfa(node)?),
+            Engine::Backtrack => Program::Backtrack(backtrack::compile(&node)?),
+        };
+        Ok(Self { program })
+    }
+
+    #[allow(dead_code)]
+    pub fn is_match(&self, input: &str) -> Result<bool, String> {
+        Ok(self.find(input)?.is_some())
+    }
+
+    /// Returns the leftmost match in `input`. Fails only if the backtracking
+    /// engine gives up.
+    pub fn find(&self, input: &str) -> Result<Option<Match>, String> {
+        let found = match &self.program {
+            Program::Nfa(nfa) => find_nfa(nfa, input),
+            Program::Backtrack(program) => find_backtrack(program, input)?,
+        };
+        Ok(found.map(|(start, end)| Match { start, end }))
+    }
+
+    /// Returns the leftmost match in `input` that starts at or after the byte
+    /// offset `start`, which must be at a char boundary.
+    pub fn find_at(&self, input: &str, start: usize) -> Result<Option<Match>, String> {
+        let found = match &self.program {
+            Program::Nfa(nfa) => find_nfa_at(nfa, input, start),
+            Program::Backtrack(program) => find_backtrack_at(program, input, start)?,
+        };
+        Ok(found.map(|(start, end)| Match { start, end }))
+    }
+
+    /// Returns the successive non-overlapping matches in `input`. An empty
+    /// match right after the previous match is skipped.
+    pub fn find_all(&self, input: &str) -> Result<Vec<Match>, String> {
+        let mut matches: Vec<Match> = vec![];
+        let mut start = 0;
+        while start <= input.len() {
+            let Some(found) = self.find_at(input, start)? else {
+                break;
+            };
+            if found.start < found.end {
+                start = found.end;
+            } else {
+                // search again after the next char
+                start = found.end + input[found.end..].chars().next().map_or(1, char::len_utf8);
+                if matches.last().is_some_and(|last| last.end == found.end) {
+                    continue;
+                }
+            }
+            matches.push(found);
+        }
+        Ok(matches)
+    }
+}
+
+/// Combines the patterns in `nodes` into one alternation that matches
+/// wherever any of them does, as grep does with several patterns. The
+/// backreferences of each pattern are renumbered to still refer to its own
+/// groups, and its named groups are numbered, so that several patterns may
+/// use the same name. No patterns match nothing.
+pub fn combine(nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        return nodes.into_iter().next().unwrap();
+    }
+    if nodes.is_empty() {
+        return Node::CharClass(CharSet::new());
+    }
+    let mut groups = 0;
+    let mut branches = vec![];
+    for mut node in nodes {
+        number_named_groups(&mut node);
+        shift_backreferences(&mut node, groups);
+        groups += count_groups(&node);
+        branches.push(node);
+    }
+    Node::Alternation(branches)
+}
+
+fn count_groups(node: &Node) -> usize {
+    let own = matches!(node, Node::Group(_) | Node::NamedGroup(_, _)) as usize;
+    own + node.children().into_iter().map(count_groups).sum::<usize>()
+}
+
+// turns the named groups of `node` and the backreferences to them into
+// numbered ones, unless a name is used twice, which is left for compiling to
+// report
+fn number_named_groups(node: &mut Node) {
+    let mut names = HashMap::new();
+    if collect_group_names(node, &mut 0, &mut names) {
+        replace_group_names(node, &names);
+    }
+}
+
+// maps each group name to the number of its group, in the order of the
+// opening parentheses; false on a duplicate name
+fn collect_group_names(
+    node: &Node,
+    groups: &mut usize,
+    names: &mut HashMap<String, usize>,
+) -> bool {
+    match node {
+        Node::Group(_) => *groups += 1,
+        Node::NamedGroup(name, _) => {
+            *groups += 1;
+            if names.insert(name.clone(), *groups).is_some() {
+                return false;
+            }
+        }
+        _ => {}
+    }
+    node.children()
+        .into_iter()
+        .all(|child| collect_group_names(child, groups, names))
+}
+
+fn replace_group_names(node: &mut Node, names: &HashMap<String, usize>) {
+    match node {
+        Node::NamedGroup(_, child) => {
+            let child = std::mem::replace(child, Box::new(Node::Empty));
+            *node = Node::Group(child);
+        }
+        // an unknown name is left for compiling to report
+        Node::NamedBackreference(name) => {
+            if let Some(&group) = names.get(name) {
+                *node = Node::Backreference(group);
+            }
+        }
+        _ => {}
+    }
+    for child in node.children_mut() {
+        replace_group_names(child, names);
+    }
+}
+
+fn shift_backreferences(node: &mut Node, offset: usize) {
+    if let Node::Backreference(n) = node {
+        *n += offset;
+    }
+    for child in node.children_mut() {
+        shift_backreferences(child, offset);
+    }
+}
+
+/// Wraps `node` so that it only matches the whole input, as grep -x does
+/// for a line.
+pub fn whole_line(node: Node) -> Node {
+    Node::Concat(vec![
+        Node::Assertion(Assertion::StartText),
+        node,
+        Node::Assertion(Assertion::EndText),
+    ])
+}
+
+/// Wraps `node` so that it only matches text that is neither preceded nor
+/// followed by a word character, as grep -w does. Unlike `\b...\b`, this
+/// also holds for a pattern that starts or ends with a non-word character.
+pub fn whole_word(node: Node) -> Node {
+    Node::Concat(vec![
+        Node::Assertion(Assertion::NotAfterWord),
+        node,
+        Node::Assertion(Assertion::NotBeforeWord),
+    ])
+}
+
+/// Returns a pattern that matches `text` literally, by escaping every
+/// character with a special meaning. Whitespace is escaped too, so that the
+/// pattern also works with the `x` flag.
+#[allow(dead_code)]
+pub fn escape(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) || c.is_whitespace() {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex() {
+        let regex = Regex::new("ab").unwrap();
+        assert!(regex.is_match("aab").unwrap());
+        assert!(!regex.is_match("ba").unwrap());
+        assert_eq!(
+            regex.find("xaby").unwrap(),
+            Some(Match { start: 1, end: 3 })
+        );
+
+        let regex = Regex::with_engine("ab", Engine::Backtrack).unwrap();
+        assert_eq!(
+            regex.find("xaby").unwrap(),
+            Some(Match { start: 1, end: 3 })
+        );
+    }
+
+    #[test]
+    fn test_regex_atomic() {
+        assert_eq!(
+            Regex::with_engine("a*+b", Engine::Nfa).unwrap_err(),
+            "Atomic groups and possessive quantifiers are not supported by the NFA engine; \
+             use the backtracking engine"
+        );
+        let regex = Regex::new("a*+b").unwrap();
+        assert_eq!(regex.find("aab").unwrap(), Some(Match { start: 0, end: 3 }));
+        let regex = Regex::new("(?>a*)b").unwrap();
+        assert_eq!(regex.find("aab").unwrap(), Some(Match { start: 0, end: 3 }));
+        assert!(!Regex::new("(?>a*)a").unwrap().is_match("aaa").unwrap());
+
+        let regex = Regex::with_engine("(?>a*)a", Engine::Backtrack).unwrap();
+        assert!(!regex.is_match("aaa").unwrap());
+        let regex = Regex::with_engine(".?(?>a*)a", Engine::Backtrack).unwrap();
+        assert_eq!(regex.find("aaa").unwrap(), None);
+        let regex = Regex::with_engine("a++b", Engine::Backtrack).unwrap();
+        assert_eq!(
+            regex.find("caab").unwrap(),
+            Some(Match { start: 1, end: 4 })
+        );
+    }
+
+    #[test]
+    fn test_regex_look_around() {
+        let regex = Regex::new("(?<=\\$)[0-9]+").unwrap();
+        assert_eq!(
+            regex.find("12 $34").unwrap(),
+            Some(Match { start: 4, end: 6 })
+        );
+        assert!(Regex::with_engine("(?<=a*)b", Engine::Nfa).is_err());
+        let regex = Regex::new("(?<=a*)b").unwrap();
+        assert_eq!(regex.find("aab").unwrap(), Some(Match { start: 2, end: 3 }));
+
+        let regex = Regex::with_engine("(?<=a*)b", Engine::Backtrack).unwrap();
+        assert_eq!(regex.find("b").unwrap(), Some(Match { start: 0, end: 1 }));
+    }
+
+    #[test]
+    fn test_regex_unicode_property() {
+        let regex = Regex::new("\\p{Greek}+").unwrap();
+        assert_eq!(
+            regex.find("alpha αλφα").unwrap(),
+            Some(Match { start: 6, end: 14 })
+        );
+        let regex = Regex::with_engine("[\\p{Lu}\\p{Nd}]\\P{L}", Engine::Backtrack).unwrap();
+        assert_eq!(
+            regex.find("aÉ3!").unwrap(),
+            Some(Match { start: 1, end: 4 })
+        );
+    }
+
+    #[test]
+    fn test_regex_backreference() {
+        // picks the backtracking engine by itself
+        let regex = Regex::new("(.)\\1").unwrap();
+        assert_eq!(
+            regex.find("abccd").unwrap(),
+            Some(Match { start: 2, end: 4 })
+        );
+        let regex = Regex::new("(?<tag>[a-z]+)=\\k<tag>").unwrap();
+        assert!(regex.is_match("x=x").unwrap());
+        assert!(!regex.is_match("x=y").unwrap());
+
+        assert_eq!(
+            Regex::with_engine("(a)\\1", Engine::Nfa).unwrap_err(),
+            "Backreferences are not supported by the NFA engine; use the backtracking engine"
+        );
+        assert!(Regex::new("(a)\\2").is_err());
+        // a backreference switches engines, but not the semantics of a loop
+        assert_eq!(
+            Regex::new("(|a)+()\\2").unwrap().find("aa").unwrap(),
+            Regex::new("(|a)+()").unwrap().find("aa").unwrap()
+        );
+        assert!(Regex::new("(a*)*\\1b")
+            .unwrap()
+            .is_match(&"a".repeat(30))
+            .is_err());
+    }
+
+    #[test]
+    fn test_find_all() {
+        let spans = |pattern: &str, input: &str, engine: Engine| -> Vec<(usize, usize)> {
+            Regex::with_engine(pattern, engine)
+                .unwrap()
+                .find_all(input)
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.start, m.end))
+                .collect()
+        };
+        for engine in [Engine::Nfa, Engine::Backtrack] {
+            assert_eq!(spans("a+", "baaxa", engine), vec![(1, 3), (4, 5)]);
+            assert_eq!(spans("x", "abc", engine), vec![]);
+            assert_eq!(spans("a*", "baa", engine), vec![(0, 0), (1, 3)]);
+            assert_eq!(spans("", "ab", engine), vec![(0, 0), (1, 1), (2, 2)]);
+            assert_eq!(spans("é|b", "aébé", engine), vec![(1, 3), (3, 4), (4, 6)]);
+            // assertions still see the text before the previous match
+            assert_eq!(spans("\\bab", "abab ab", engine), vec![(0, 2), (5, 7)]);
+            assert_eq!(spans("(?<=a)b", "abab", engine), vec![(1, 2), (3, 4)]);
+            assert_eq!(spans("^a", "aa", engine), vec![(0, 1)]);
+        }
+        let regex = Regex::new("b").unwrap();
+        assert_eq!(
+            regex.find_at("abab", 2),
+            Ok(Some(Match { start: 3, end: 4 }))
+        );
+        assert_eq!(regex.find_at("abab", 4), Ok(None));
+    }
+
+    #[test]
+    fn test_combine() {
+        let combined = |patterns: &[&str]| {
+            let nodes = patterns
+                .iter()
+                .map(|pattern| parse(lex(pattern).unwrap()).unwrap())
+                .collect();
+            let node = combine(nodes);
+            let engine = Engine::for_node(&node);
+            Regex::from_node(node, engine).unwrap()
+        };
+        let regex = combined(&["cat", "dog", "b(ir)d"]);
+        assert!(regex.is_match("a dog").unwrap());
+        assert!(regex.is_match("bird").unwrap());
+        assert!(!regex.is_match("cow").unwrap());
+        assert_eq!(
+            regex.find_all("dog cat").unwrap(),
+            vec![Match { start: 0, end: 3 }, Match { start: 4, end: 7 }]
+        );
+
+        // each pattern refers to its own groups
+        let regex = combined(&["(a)(b)\\2", "(x)\\1"]);
+        assert!(regex.is_match("abb").unwrap());
+        assert!(regex.is_match("xx").unwrap());
+        assert!(!regex.is_match("xb").unwrap());
+        assert_eq!(
+            combine(vec![
+                parse(lex("(a)").unwrap()).unwrap(),
+                parse(lex("(b)\\1").unwrap()).unwrap()
+            ]),
+            Node::Alternation(vec![
+                Node::Group(Box::new(Node::Literal('a'))),
+                Node::Concat(vec![
+                    Node::Group(Box::new(Node::Literal('b'))),
+                    Node::Backreference(2)
+                ])
+            ])
+        );
+
+        // and to its own names
+        let regex = combined(&["(?<n>a)\\k<n>", "(?<n>b)\\k<n>"]);
+        assert!(regex.is_match("aa").unwrap());
+        assert!(regex.is_match("bb").unwrap());
+        assert!(!regex.is_match("ab").unwrap());
+        assert!(!regex.is_match("ba").unwrap());
+        let regex = combined(&["x", "(?<n>a)(b)\\k<n>\\2"]);
+        assert!(regex.is_match("abab").unwrap());
+        assert!(!regex.is_match("abba").unwrap());
+        let combined_err = |patterns: &[&str]| {
+            let nodes = patterns
+                .iter()
+                .map(|pattern| parse(lex(pattern).unwrap()).unwrap())
+                .collect();
+            let node = combine(nodes);
+            let engine = Engine::for_node(&node);
+            Regex::from_node(node, engine).unwrap_err()
+        };
+        assert_eq!(
+            combined_err(&["(?<n>a)", "\\k<n>"]),
+            "Unknown group name: n"
+        );
+        assert_eq!(
+            combined_err(&["x", "(?<n>a)(?<n>b)\\k<n>"]),
+            "Duplicate group name: n"
+        );
+
+        assert!(!combined(&[]).is_match("").unwrap());
+        assert!(combined(&["x", ""]).is_match("abc").unwrap());
+    }
+
+    #[test]
+    fn test_whole_line_and_word() {
+        let compile = |wrap: fn(Node) -> Node, pattern: &str, engine: Engine| {
+            Regex::from_node(wrap(parse(lex(pattern).unwrap()).unwrap()), engine).unwrap()
+        };
+        for engine in [Engine::Nfa, Engine::Backtrack] {
+            let regex = compile(whole_line, "a|ab", engine);
+            assert!(regex.is_match("ab").unwrap());
+            assert!(regex.is_match("a").unwrap());
+            assert!(!regex.is_match("abc").unwrap());
+            assert!(!regex.is_match("xa").unwrap());
+
+            let regex = compile(whole_word, "foo|foobar", engine);
+            assert_eq!(
+                regex.find("a foobar").unwrap(),
+                Some(Match { start: 2, end: 8 })
+            );
+            assert!(regex.is_match("(foo)").unwrap());
+            assert!(!regex.is_match("foobarbaz food").unwrap());
+            assert!(!regex.is_match("_foo").unwrap());
+
+            // unlike \b, a non-word edge still needs a non-word neighbor
+            let regex = compile(whole_word, "@x", engine);
+            assert!(regex.is_match("an @x").unwrap());
+            assert!(!regex.is_match("a@x").unwrap());
+            assert!(!regex.is_match("@xy").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a.b*c"), "a\\.b\\*c");
+        assert_eq!(escape("(x|y) [z]"), "\\(x\\|y\\)\\ \\[z\\]");
+        assert_eq!(escape("\\Q"), "\\\\Q");
+        assert_eq!(escape("日本"), "日本");
+
+        // round trip over random strings, with a xorshift generator
+        let alphabet: Vec<char> = "ab\\.+*?()|[]{}^$#&-~ \t\nQEbBpPxu0123:=!<>,é日😀"
+            .chars()
+            .collect();
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..500 {
+            let len = (next() % 12) as usize;
+            let text: String = (0..len)
+                .map(|_| alphabet[(next() % alphabet.len() as u64) as usize])
+                .collect();
+            let pattern = escape(&text);
+            let anchored = format!("^{}$", pattern);
+            for engine in [Engine::Nfa, Engine::Backtrack] {
+                let regex = Regex::with_engine(&anchored, engine).unwrap();
+                assert_eq!(
+                    regex.find(&text).unwrap(),
+                    Some(Match {
+                        start: 0,
+                        end: text.len()
+                    }),
+                    "{:?} -> {:?}",
+                    text,
+                    pattern
+                );
+            }
+            let extended = format!("(?x){}", pattern);
+            assert!(Regex::new(&extended).unwrap().is_match(&text).unwrap());
+            let quoted = format!("\\Q{}\\E", text);
+            if !text.contains("\\E") {
+                assert!(Regex::new(&quoted).unwrap().is_match(&text).unwrap());
+            }
+        }
+    }
+
+    // builds a random pattern out of the atoms, nested up to `depth` levels
+    fn random_pattern(next: &mut impl FnMut() -> u64, depth: usize) -> String {
+        const ATOMS: &[&str] = &[
+            "a", "b", ".", "[ab]", "^", "$", "\\b", "", "(?=a)", "(?<!b)",
+        ];
+        const QUANTIFIERS: &[&str] = &[
+            "*", "+", "?", "*?", "+?", "??", "{2}", "{0,2}", "{1,}", "{0,2}?",
+        ];
+        let pick = |items: &[&str], n: u64| items[(n % items.len() as u64) as usize].to_string();
+        match if depth == 0 { 0 } else { next() % 6 } {
+            0 | 1 => pick(ATOMS, next()),
+            2 => random_pattern(next, depth - 1) + &random_pattern(next, depth - 1),
+            3 => format!(
+                "({}|{})",
+                random_pattern(next, depth - 1),
+                random_pattern(next, depth - 1)
+            ),
+            _ => format!(
+                "({}){}",
+                random_pattern(next, depth - 1),
+                pick(QUANTIFIERS, next())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..3000 {
+            let pattern = random_pattern(&mut next, 4);
+            let nfa = Regex::with_engine(&pattern, Engine::Nfa).unwrap();
+            let backtrack = Regex::with_engine(&pattern, Engine::Backtrack).unwrap();
+            for _ in 0..3 {
+                let len = (next() % 6) as usize;
+                let input: String = (0..len)
+                    .map(|_| ['a', 'b', ' '][(next() % 3) as usize])
+                    .collect();
+                assert_eq!(
+                    nfa.find(&input).unwrap(),
+                    backtrack.find(&input).unwrap(),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+}
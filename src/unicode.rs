@@ -0,0 +1,170 @@
+mod tables;
+
+use crate::charset::CharSet;
+use tables::{GENERAL_CATEGORY, SCRIPT};
+
+// general categories and their groups, as (abbreviation, long name)
+const CATEGORY_NAMES: &[(&str, &str)] = &[
+    ("L", "Letter"),
+    ("LC", "Cased_Letter"),
+    ("Lu", "Uppercase_Letter"),
+    ("Ll", "Lowercase_Letter"),
+    ("Lt", "Titlecase_Letter"),
+    ("Lm", "Modifier_Letter"),
+    ("Lo", "Other_Letter"),
+    ("M", "Mark"),
+    ("Mn", "Nonspacing_Mark"),
+    ("Mc", "Spacing_Mark"),
+    ("Me", "Enclosing_Mark"),
+    ("N", "Number"),
+    ("Nd", "Decimal_Number"),
+    ("Nl", "Letter_Number"),
+    ("No", "Other_Number"),
+    ("P", "Punctuation"),
+    ("Pc", "Connector_Punctuation"),
+    ("Pd", "Dash_Punctuation"),
+    ("Ps", "Open_Punctuation"),
+    ("Pe", "Close_Punctuation"),
+    ("Pi", "Initial_Punctuation"),
+    ("Pf", "Final_Punctuation"),
+    ("Po", "Other_Punctuation"),
+    ("S", "Symbol"),
+    ("Sm", "Math_Symbol"),
+    ("Sc", "Currency_Symbol"),
+    ("Sk", "Modifier_Symbol"),
+    ("So", "Other_Symbol"),
+    ("Z", "Separator"),
+    ("Zs", "Space_Separator"),
+    ("Zl", "Line_Separator"),
+    ("Zp", "Paragraph_Separator"),
+    ("C", "Other"),
+    ("Cc", "Control"),
+    ("Cf", "Format"),
+    ("Cs", "Surrogate"),
+    ("Co", "Private_Use"),
+    ("Cn", "Unassigned"),
+];
+
+/// Returns the set of characters with a Unicode property, as written in
+/// `\p{...}`.
+///
+/// `name` is a general category (`L`, `Lu`, `Uppercase_Letter`), a script
+/// (`Greek`, `Han`), either of them with an explicit property (`gc=Lu`,
+/// `sc=Greek`), or one of `Any`, `ASCII` and `Assigned`. Names are matched
+/// ignoring case, spaces, `_` and `-`.
+pub fn property(name: &str) -> Option<CharSet> {
+    if let Some((property, value)) = name.split_once('=') {
+        return match loose(property).as_str() {
+            "gc" | "generalcategory" => general_category(value),
+            "sc" | "script" => script(value),
+            _ => None,
+        };
+    }
+    match loose(name).as_str() {
+        "any" => Some(CharSet::from_ranges([('\0', char::MAX)])),
+        "ascii" => Some(CharSet::from_ranges([('\0', '\x7F')])),
+        "assigned" => general_category("Cn").map(|set| set.negate()),
+        _ => general_category(name).or_else(|| script(name)),
+    }
+}
+
+fn general_category(name: &str) -> Option<CharSet> {
+    let name = loose(name);
+    let &(abbreviation, _) = CATEGORY_NAMES
+        .iter()
+        .find(|(abbreviation, long)| loose(abbreviation) == name || loose(long) == name)?;
+    let included = |category: &str| match abbreviation {
+        "LC" => matches!(category, "Lu" | "Ll" | "Lt"),
+        // a one letter group includes its categories
+        _ => category.starts_with(abbreviation),
+    };
+    Some(CharSet::from_ranges(
+        GENERAL_CATEGORY
+            .iter()
+            .filter(|(category, _)| included(category))
+            .flat_map(|(_, ranges)| ranges.iter().copied()),
+    ))
+}
+
+fn script(name: &str) -> Option<CharSet> {
+    let name = loose(name);
+    SCRIPT
+        .iter()
+        .find(|(script, _)| loose(script) == name)
+        .map(|(_, ranges)| CharSet::from_ranges(ranges.iter().copied()))
+}
+
+fn loose(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_general_category() {
+        let letter = property("L").unwrap();
+        assert!(letter.contains('a'));
+        assert!(letter.contains('Ω'));
+        assert!(letter.contains('漢'));
+        assert!(!letter.contains('1'));
+        assert!(!letter.contains(' '));
+        assert_eq!(property("Letter"), Some(letter.clone()));
+        assert_eq!(property("gc=L"), Some(letter));
+
+        let upper = property("Lu").unwrap();
+        assert!(upper.contains('A'));
+        assert!(upper.contains('Ω'));
+        assert!(!upper.contains('a'));
+        assert_eq!(property("uppercase letter"), Some(upper.clone()));
+        assert_eq!(property("UPPERCASE-LETTER"), Some(upper));
+
+        let cased = property("LC").unwrap();
+        assert!(cased.contains('a') && cased.contains('A'));
+        assert!(!cased.contains('漢'));
+
+        assert!(property("Nd").unwrap().contains('٣'));
+        assert!(property("Sc").unwrap().contains('€'));
+        assert!(property("Zs").unwrap().contains('\u{3000}'));
+        assert!(property("Cn").unwrap().contains('\u{378}'));
+        assert!(!property("Assigned").unwrap().contains('\u{378}'));
+        assert!(property("ASCII").unwrap().contains('\x7F'));
+        assert!(!property("ASCII").unwrap().contains('é'));
+    }
+
+    #[test]
+    fn test_script() {
+        let greek = property("Greek").unwrap();
+        assert!(greek.contains('α'));
+        assert!(greek.contains('Ω'));
+        assert!(!greek.contains('a'));
+        assert_eq!(property("sc=greek"), Some(greek));
+
+        let han = property("Han").unwrap();
+        assert!(han.contains('漢'));
+        assert!(han.contains('\u{20000}'));
+        assert!(!han.contains('あ'));
+        // iteration marks and Hangzhou numerals are Han, but the prolonged
+        // sound mark is shared by Hiragana and Katakana
+        assert!(han.contains('々') && han.contains('〇') && han.contains('〡'));
+        assert!(property("Common").unwrap().contains('ー'));
+        assert!(!property("Katakana").unwrap().contains('ー'));
+        assert!(property("Hiragana").unwrap().contains('あ'));
+        assert!(property("Cyrillic").unwrap().contains('ж'));
+        let latin = property("Latin").unwrap();
+        assert!(latin.contains('é'));
+        assert!(latin.contains('ª') && latin.contains('ʰ'));
+        assert!(!latin.contains('ˆ'));
+        assert!(property("Common").unwrap().contains('1'));
+        assert!(property("Inherited").unwrap().contains('\u{301}'));
+        assert!(property("Old_Italic").unwrap().contains('\u{10300}'));
+
+        assert_eq!(property("Klingon"), None);
+        assert_eq!(property("sc=Lu"), None);
+        assert_eq!(property("foo=Greek"), None);
+    }
+}
@@ -0,0 +1,46 @@
+use std::process::{Command, Output};
+
+fn dot(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dot"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_arguments() {
+    let output = dot(&["a|b"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("digraph finite_state_machine {"));
+
+    // a pattern that starts with -
+    let output = dot(&["--", "-a"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("label=\"-\""));
+
+    let output = dot(&["--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("Usage: dot [OPTION]... PATTERN\n"));
+    let output = dot(&["--version"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("dot {}\n", env!("CARGO_PKG_VERSION"))
+    );
+
+    for (args, error) in [
+        (&[][..], "dot: no pattern given\n"),
+        (&["a", "b"][..], "dot: extra operand 'b'\n"),
+        (&["-x", "a"][..], "dot: invalid option -- 'x'\n"),
+        (&["(a"][..], "dot: Unclosed group\n"),
+    ] {
+        let output = dot(args);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr).unwrap().starts_with(error));
+    }
+}
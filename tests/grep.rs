@@ -0,0 +1,580 @@
+use std::process::{Command, Output};
+
+// runs l2r2grep in tests/fixtures and returns its standard output
+fn grep(args: &[&str]) -> String {
+    String::from_utf8(run(args).stdout).unwrap()
+}
+
+// returns the standard output of l2r2grep as it is, for output that is not
+// valid UTF-8
+fn grep_bytes(args: &[&str]) -> Vec<u8> {
+    run(args).stdout
+}
+
+// returns the exit code and the standard error of l2r2grep
+fn status(args: &[&str]) -> (i32, String) {
+    let output = run(args);
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+// runs l2r2grep with a standard output that is closed before it writes,
+// feeding `input` to it, and returns its exit code and standard error
+fn closed_stdout(args: &[&str], input: &[u8]) -> (Option<i32>, String) {
+    use std::io::Write;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_l2r2grep"))
+        .args(args)
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    // l2r2grep may stop reading early
+    let _ = child.stdin.take().unwrap().write_all(input);
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_l2r2grep"))
+        .args(args)
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_files() {
+    assert_eq!(grep(&["an", "fruits.txt"]), "banana\n");
+    assert_eq!(
+        grep(&["an", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:banana\nvegetables.txt:eggplant\n"
+    );
+    assert_eq!(grep(&["an", "dir"]), "");
+    assert_eq!(
+        grep(&["-r", "an|al", "dir"]),
+        "dir/greek.txt:alpha\ndir/sub/desserts.txt:banana split\n"
+    );
+    assert_eq!(
+        grep(&["-r", "split", "dir/sub/desserts.txt"]),
+        "banana split\n"
+    );
+    assert_eq!(
+        grep(&["an", "missing.txt", "fruits.txt"]),
+        "fruits.txt:banana\n"
+    );
+}
+
+#[test]
+fn test_output_flags() {
+    assert_eq!(grep(&["-n", "ap", "fruits.txt"]), "1:apple\n4:apricot\n");
+    assert_eq!(
+        grep(&["-n", "ap", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:1:apple\nfruits.txt:4:apricot\n"
+    );
+    assert_eq!(grep(&["-c", "a", "fruits.txt"]), "3\n");
+    assert_eq!(
+        grep(&["-c", "ap", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:2\nvegetables.txt:0\n"
+    );
+    assert_eq!(
+        grep(&["-l", "an", "fruits.txt", "vegetables.txt", "dir/greek.txt"]),
+        "fruits.txt\nvegetables.txt\n"
+    );
+    assert_eq!(
+        grep(&["-L", "an", "fruits.txt", "vegetables.txt", "dir/greek.txt"]),
+        "dir/greek.txt\n"
+    );
+    assert_eq!(
+        grep(&["-rl", "ta", "."]),
+        "./dir/greek.txt\n./vegetables.txt\n"
+    );
+}
+
+#[test]
+fn test_filename_flags() {
+    assert_eq!(grep(&["-H", "an", "fruits.txt"]), "fruits.txt:banana\n");
+    assert_eq!(
+        grep(&["-h", "an", "fruits.txt", "vegetables.txt"]),
+        "banana\neggplant\n"
+    );
+    assert_eq!(grep(&["-Hn", "^c", "fruits.txt"]), "fruits.txt:3:cherry\n");
+    assert_eq!(grep(&["-Hc", "^c", "fruits.txt"]), "fruits.txt:1\n");
+    assert_eq!(grep(&["-hH", "an", "fruits.txt"]), "fruits.txt:banana\n");
+}
+
+#[test]
+fn test_match_modes() {
+    assert_eq!(grep(&["-v", "a", "fruits.txt"]), "cherry\n");
+    assert_eq!(grep(&["-vc", "a", "fruits.txt"]), "1\n");
+    assert_eq!(
+        grep(&["-vl", "o|t", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt\n"
+    );
+    assert_eq!(grep(&["-x", "app|apple", "fruits.txt"]), "apple\n");
+    assert_eq!(grep(&["-x", "an", "fruits.txt"]), "");
+    assert_eq!(
+        grep(&["-xn", "cherry|.*cot", "fruits.txt"]),
+        "3:cherry\n4:apricot\n"
+    );
+    assert_eq!(
+        grep(&["-w", "banana", "-r", "dir"]),
+        "dir/sub/desserts.txt:banana split\n"
+    );
+    assert_eq!(grep(&["-w", "ban", "-r", "dir"]), "");
+    assert_eq!(
+        grep(&["-wv", "split|beta", "-r", "dir"]),
+        "dir/greek.txt:alpha\n"
+    );
+    // -x wins over -w
+    assert_eq!(grep(&["-wx", "banana", "dir/sub/desserts.txt"]), "");
+}
+
+#[test]
+fn test_only_matching() {
+    assert_eq!(grep(&["-o", "an|ap", "fruits.txt"]), "ap\nan\nan\nap\n");
+    assert_eq!(grep(&["-on", "a.", "vegetables.txt"]), "1:ar\n2:at\n3:an\n");
+    assert_eq!(grep(&["-oc", "a", "fruits.txt"]), "3\n");
+    assert_eq!(grep(&["-ov", "a", "fruits.txt"]), "");
+    // empty matches are not printed
+    assert_eq!(grep(&["-o", "x*", "fruits.txt"]), "");
+    assert_eq!(grep(&["-c", "x*", "fruits.txt"]), "4\n");
+}
+
+#[test]
+fn test_backtracking_features() {
+    // atomic groups, possessive quantifiers and unbounded lookbehind run on
+    // the backtracking engine
+    assert_eq!(grep(&["-o", "(?>p*)l", "fruits.txt"]), "ppl\n");
+    assert_eq!(grep(&["-o", "(?>p*)p", "fruits.txt"]), "");
+    assert_eq!(grep(&["-o", "a++n", "fruits.txt"]), "an\nan\n");
+    assert_eq!(grep(&["-o", "(?<=^a.*)c", "fruits.txt"]), "c\n");
+}
+
+#[test]
+fn test_color() {
+    assert_eq!(
+        grep(&["--color=always", "an", "dir/sub/desserts.txt"]),
+        "b\x1b[01;31man\x1b[m\x1b[01;31man\x1b[ma split\n"
+    );
+    assert_eq!(
+        grep(&["--color=always", "-Hno", "sp", "dir/sub/desserts.txt"]),
+        "\x1b[35mdir/sub/desserts.txt\x1b[m\x1b[36m:\x1b[m\x1b[32m1\x1b[m\x1b[36m:\x1b[m\x1b[01;31msp\x1b[m\n"
+    );
+    assert_eq!(
+        grep(&["--colour=always", "-l", "sp", "dir/sub/desserts.txt"]),
+        "\x1b[35mdir/sub/desserts.txt\x1b[m\n"
+    );
+    // inverted lines have nothing to highlight
+    assert_eq!(
+        grep(&["--color=always", "-v", "an", "fruits.txt"]),
+        "apple\ncherry\napricot\n"
+    );
+    // standard output is not a terminal here
+    assert_eq!(
+        grep(&["--color", "sp", "dir/sub/desserts.txt"]),
+        "banana split\n"
+    );
+    assert_eq!(
+        grep(&["--color=never", "sp", "dir/sub/desserts.txt"]),
+        "banana split\n"
+    );
+    assert_eq!(
+        grep(&["--color=sometimes", "sp", "dir/sub/desserts.txt"]),
+        ""
+    );
+}
+
+#[test]
+fn test_context() {
+    assert_eq!(
+        grep(&["-nC1", "apple|apricot", "fruits.txt"]),
+        "1:apple\n2-banana\n3-cherry\n4:apricot\n"
+    );
+    assert_eq!(grep(&["-A", "1", "apple", "fruits.txt"]), "apple\nbanana\n");
+    assert_eq!(grep(&["-B2", "^b", "fruits.txt"]), "apple\nbanana\n");
+    assert_eq!(
+        grep(&["-A1", "apple|cherry", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:apple\nfruits.txt-banana\nfruits.txt:cherry\nfruits.txt-apricot\n"
+    );
+    // groups that are not adjacent are separated, also across files
+    assert_eq!(
+        grep(&["-nB1", "potato|eggplant|alpha", "-r", "."]),
+        "./dir/greek.txt:1:alpha\n--\n./vegetables.txt-1-carrot\n./vegetables.txt:2:potato\n./vegetables.txt:3:eggplant\n"
+    );
+    assert_eq!(
+        grep(&["-A0", "apple|cherry", "fruits.txt"]),
+        "apple\n--\ncherry\n"
+    );
+    assert_eq!(grep(&["-vA1", "a", "fruits.txt"]), "cherry\napricot\n");
+    // context is not printed with -o, -c and -l
+    assert_eq!(grep(&["-oC1", "ch", "fruits.txt"]), "ch\n");
+    assert_eq!(grep(&["-cC1", "ch", "fruits.txt"]), "1\n");
+    assert_eq!(grep(&["-A", "x", "ch", "fruits.txt"]), "");
+}
+
+#[test]
+fn test_multiple_patterns() {
+    assert_eq!(
+        grep(&["-e", "apple", "-e", "cot", "fruits.txt"]),
+        "apple\napricot\n"
+    );
+    assert_eq!(grep(&["-eapple", "-ecot", "-c", "fruits.txt"]), "2\n");
+    // as in a file, each line of -e is a pattern
+    assert_eq!(
+        grep(&["-e", "apple\ncot", "fruits.txt"]),
+        "apple\napricot\n"
+    );
+    assert_eq!(grep(&["-e", "(a)\\1\n(p)\\1", "-c", "fruits.txt"]), "1\n");
+    // each pattern has its own group names
+    assert_eq!(
+        grep(&["-e", "(?<c>p)\\k<c>", "-e", "(?<c>r)\\k<c>", "fruits.txt"]),
+        "apple\ncherry\n"
+    );
+    // with -e, the first operand is a file
+    assert_eq!(
+        grep(&["-e", "an", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:banana\nvegetables.txt:eggplant\n"
+    );
+    assert_eq!(
+        grep(&[
+            "-f",
+            "patterns.txt",
+            "-r",
+            "fruits.txt",
+            "vegetables.txt",
+            "dir"
+        ]),
+        "fruits.txt:cherry\nvegetables.txt:carrot\ndir/greek.txt:alpha\n"
+    );
+    assert_eq!(
+        grep(&["-f", "patterns.txt", "-e", "potato", "vegetables.txt"]),
+        "carrot\npotato\n"
+    );
+    assert_eq!(
+        grep(&["-o", "-e", "an", "-e", "b", "fruits.txt"]),
+        "b\nan\nan\n"
+    );
+    assert_eq!(
+        grep(&["-x", "-e", "app", "-e", "apple", "fruits.txt"]),
+        "apple\n"
+    );
+    // each pattern has its own groups
+    assert_eq!(
+        grep(&[
+            "-e",
+            "(p)\\1",
+            "-e",
+            "(r)\\1",
+            "fruits.txt",
+            "vegetables.txt"
+        ]),
+        "fruits.txt:apple\nfruits.txt:cherry\nvegetables.txt:carrot\n"
+    );
+    // no patterns match nothing, and an empty pattern matches everything
+    assert_eq!(grep(&["-f", "empty.txt", "fruits.txt"]), "");
+    assert_eq!(grep(&["-c", "-e", "", "fruits.txt"]), "4\n");
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(status(&["an", "fruits.txt"]), (0, String::new()));
+    assert_eq!(status(&["xyz", "fruits.txt"]), (1, String::new()));
+    assert_eq!(status(&["-v", ".", "fruits.txt"]), (1, String::new()));
+    assert_eq!(status(&["-c", "xyz", "fruits.txt"]), (1, String::new()));
+    assert_eq!(status(&["-L", "an", "fruits.txt"]), (1, String::new()));
+    assert_eq!(status(&["-L", "xyz", "fruits.txt"]), (0, String::new()));
+
+    // errors go to the standard error, and win over a match
+    assert_eq!(
+        status(&["an", "missing.txt", "fruits.txt"]),
+        (
+            2,
+            "l2r2grep: missing.txt: No such file or directory\n".to_string()
+        )
+    );
+    assert_eq!(
+        grep(&["an", "missing.txt", "fruits.txt"]),
+        "fruits.txt:banana\n"
+    );
+    assert_eq!(
+        status(&["an", "dir"]),
+        (2, "l2r2grep: dir: Is a directory\n".to_string())
+    );
+    assert_eq!(
+        status(&["(an", "fruits.txt"]),
+        (2, "l2r2grep: Unclosed group\n".to_string())
+    );
+    assert_eq!(
+        status(&["[an", "fruits.txt"]),
+        (2, "l2r2grep: Unclosed character class\n".to_string())
+    );
+    assert_eq!(
+        status(&["-e", "a", "-e", "\\1", "fruits.txt"]),
+        (2, "l2r2grep: Invalid backreference: \\1\n".to_string())
+    );
+    assert_eq!(
+        status(&["-f", "missing.txt", "fruits.txt"]),
+        (
+            2,
+            "l2r2grep: missing.txt: No such file or directory\n".to_string()
+        )
+    );
+    assert_eq!(status(&["-A", "x", "a", "fruits.txt"]).0, 2);
+    assert_eq!(status(&["-Z", "a", "fruits.txt"]).0, 2);
+    assert_eq!(status(&[]).0, 2);
+    assert_eq!(grep(&[]), "");
+}
+
+#[test]
+fn test_quiet() {
+    assert_eq!(grep(&["-q", "an", "fruits.txt"]), "");
+    assert_eq!(status(&["-q", "an", "fruits.txt"]), (0, String::new()));
+    assert_eq!(status(&["-q", "xyz", "fruits.txt"]), (1, String::new()));
+    assert_eq!(grep(&["-qc", "an", "fruits.txt"]), "");
+    // a selected line makes -q succeed despite errors
+    assert_eq!(status(&["-q", "an", "missing.txt", "fruits.txt"]).0, 0);
+    assert_eq!(status(&["-q", "xyz", "missing.txt", "fruits.txt"]).0, 2);
+}
+
+#[test]
+fn test_arguments() {
+    let help = grep(&["--help"]);
+    assert!(help.starts_with("Usage: l2r2grep [OPTION]... PATTERN [FILE]...\n"));
+    assert!(help.contains("  -n, --line-number "));
+    assert!(help.contains("      --color[=WHEN] "));
+    assert_eq!(status(&["--help"]), (0, String::new()));
+    assert_eq!(
+        grep(&["--version"]),
+        format!("l2r2grep {}\n", env!("CARGO_PKG_VERSION"))
+    );
+
+    // long options, and options after operands
+    assert_eq!(
+        grep(&["--line-number", "--after-context=1", "apple", "fruits.txt"]),
+        "1:apple\n2-banana\n"
+    );
+    assert_eq!(grep(&["--regexp", "apple", "fruits.txt", "-c"]), "1\n");
+    assert_eq!(grep(&["-cean", "fruits.txt"]), "1\n");
+
+    // patterns and files that start with -
+    assert_eq!(grep(&["-e", "-", "-c", "fruits.txt"]), "0\n");
+    assert_eq!(grep(&["--", "-o", "fruits.txt"]), "");
+    assert_eq!(status(&["--", "-o", "fruits.txt"]).0, 1);
+    assert_eq!(grep(&["-c", "--", "an", "-n"]), "");
+    assert_eq!(
+        status(&["-c", "--", "an", "-n"]),
+        (2, "l2r2grep: -n: No such file or directory\n".to_string())
+    );
+
+    // wrong command lines
+    let usage = "Usage: l2r2grep [OPTION]... PATTERN [FILE]...\nTry 'l2r2grep --help' for more information.\n";
+    assert_eq!(
+        status(&["--bogus", "a"]),
+        (
+            2,
+            format!("l2r2grep: unrecognized option '--bogus'\n{}", usage)
+        )
+    );
+    assert_eq!(
+        status(&["-nZ", "a"]),
+        (2, format!("l2r2grep: invalid option -- 'Z'\n{}", usage))
+    );
+    assert_eq!(
+        status(&["a", "-e"]),
+        (
+            2,
+            format!("l2r2grep: option requires an argument -- 'e'\n{}", usage)
+        )
+    );
+    assert_eq!(
+        status(&["--count=1", "a"]),
+        (
+            2,
+            format!(
+                "l2r2grep: option '--count' doesn't allow an argument\n{}",
+                usage
+            )
+        )
+    );
+    assert_eq!(
+        status(&[]),
+        (2, format!("l2r2grep: no pattern given\n{}", usage))
+    );
+}
+
+#[test]
+fn test_binary_files() {
+    assert_eq!(
+        grep(&["kiwi", "image.bin"]),
+        "Binary file image.bin matches\n"
+    );
+    assert_eq!(status(&["kiwi", "image.bin"]).0, 0);
+    assert_eq!(grep(&["xyz", "image.bin"]), "");
+    assert_eq!(
+        grep(&["-r", "kiwi|eggplant", "."]),
+        "Binary file ./image.bin matches\n./vegetables.txt:eggplant\n"
+    );
+    // -c, -l and -q do not print lines anyway
+    assert_eq!(grep(&["-c", "kiwi", "image.bin"]), "2\n");
+    assert_eq!(grep(&["-l", "kiwi", "image.bin"]), "image.bin\n");
+
+    // -a and --binary-files=text search it as text
+    assert_eq!(
+        grep(&["-n", "-a", "kiwi", "image.bin"]),
+        "2:kiwi\n3:kiwi juice\n"
+    );
+    assert_eq!(
+        grep(&["--binary-files=text", "-o", "ju.*", "image.bin"]),
+        "juice\n"
+    );
+
+    // -I and --binary-files=without-match skip it
+    assert_eq!(grep(&["-I", "kiwi", "image.bin"]), "");
+    assert_eq!(status(&["-I", "kiwi", "image.bin"]).0, 1);
+    assert_eq!(
+        grep(&[
+            "--binary-files=without-match",
+            "-c",
+            "kiwi|an",
+            "image.bin",
+            "fruits.txt"
+        ]),
+        "image.bin:0\nfruits.txt:1\n"
+    );
+    assert_eq!(
+        status(&["--binary-files=data", "kiwi", "image.bin"]),
+        (
+            2,
+            "l2r2grep: invalid argument 'data' for '--binary-files'\n".to_string()
+        )
+    );
+
+    // only the first block is checked for a NUL
+    let path = std::env::temp_dir().join(format!("l2r2grep-nul-{}.txt", std::process::id()));
+    let mut text = "x".repeat(100_000).into_bytes();
+    text.extend_from_slice(b"\0\nkiwi\n");
+    std::fs::write(&path, text).unwrap();
+    assert_eq!(grep(&["-h", "kiwi", path.to_str().unwrap()]), "kiwi\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_max_count() {
+    assert_eq!(grep(&["-m", "1", "a", "fruits.txt"]), "apple\n");
+    assert_eq!(
+        grep(&["--max-count=2", "-n", "a", "fruits.txt"]),
+        "1:apple\n2:banana\n"
+    );
+    assert_eq!(
+        grep(&["-m2", "-c", "a", "fruits.txt", "vegetables.txt"]),
+        "fruits.txt:2\nvegetables.txt:2\n"
+    );
+    assert_eq!(grep(&["-m1", "-v", "apple", "fruits.txt"]), "banana\n");
+    assert_eq!(grep(&["-m1", "-o", "an", "fruits.txt"]), "an\nan\n");
+    // the context after the last line is still printed
+    assert_eq!(
+        grep(&["-m1", "-nA2", "a", "fruits.txt"]),
+        "1:apple\n2-banana\n3-cherry\n"
+    );
+    assert_eq!(grep(&["-m0", "a", "fruits.txt"]), "");
+    assert_eq!(status(&["-m0", "a", "fruits.txt"]).0, 1);
+    assert_eq!(
+        status(&["-m", "x", "a", "fruits.txt"]),
+        (2, "l2r2grep: invalid max count 'x'\n".to_string())
+    );
+
+    // the standard input is not read to the end
+    let mut child = Command::new(env!("CARGO_BIN_EXE_l2r2grep"))
+        .args(["-m1", "y"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    // writing fails once l2r2grep has exited, long before the end
+    let writer = std::thread::spawn(move || {
+        use std::io::Write;
+        (0..100_000).all(|_| stdin.write_all(b"yes\n").is_ok())
+    });
+    let output = child.wait_with_output().unwrap();
+    assert!(!writer.join().unwrap());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "yes\n");
+}
+
+#[test]
+fn test_byte_offset() {
+    assert_eq!(
+        grep(&["-b", "a", "fruits.txt"]),
+        "0:apple\n6:banana\n20:apricot\n"
+    );
+    assert_eq!(grep(&["-bo", "an", "fruits.txt"]), "7:an\n9:an\n");
+    assert_eq!(
+        grep(&["-Hnb", "-C1", "cherry", "fruits.txt"]),
+        "fruits.txt-2-6-banana\nfruits.txt:3:13:cherry\nfruits.txt-4-20-apricot\n"
+    );
+    assert_eq!(grep(&["--byte-offset", "-c", "a", "fruits.txt"]), "3\n");
+    // offsets count the bytes of the file, with \r\n and multibyte characters
+    let path = std::env::temp_dir().join(format!("l2r2grep-offset-{}.txt", std::process::id()));
+    std::fs::write(&path, "é\r\nxé x\n").unwrap();
+    assert_eq!(grep(&["-hbo", "x", path.to_str().unwrap()]), "4:x\n8:x\n");
+    // and with bytes that are not valid UTF-8
+    std::fs::write(&path, b"caf\xe9 ok\n\xff\xfe\xe9ok\n").unwrap();
+    assert_eq!(
+        grep(&["-hbo", "ok", path.to_str().unwrap()]),
+        "5:ok\n11:ok\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_lines_as_read() {
+    // lines are printed with the bytes and the terminator they were read
+    // with, even if they are not valid UTF-8
+    let path = std::env::temp_dir().join(format!("l2r2grep-bytes-{}.txt", std::process::id()));
+    std::fs::write(&path, b"caf\xe9\r\nok\r\nno end").unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(grep_bytes(&["caf", path]), b"caf\xe9\r\n");
+    assert_eq!(grep_bytes(&["-A1", "caf", path]), b"caf\xe9\r\nok\r\n");
+    assert_eq!(grep_bytes(&["-B1", "ok", path]), b"caf\xe9\r\nok\r\n");
+    assert_eq!(grep_bytes(&["end", path]), b"no end\n");
+    // $ still matches before \r\n, and the invalid byte as one char
+    assert_eq!(grep_bytes(&["-o", "f.$", path]), b"f\xe9\n");
+    assert_eq!(
+        grep_bytes(&["--color=always", "f.", path]),
+        b"ca\x1b[01;31mf\xe9\x1b[m\r\n"
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_closed_output() {
+    // a reader that goes away, as head does, stops l2r2grep quietly
+    let lines: String = (0..100_000).map(|i| format!("{}\n", i)).collect();
+    assert_eq!(
+        closed_stdout(&["1"], lines.as_bytes()),
+        (Some(0), String::new())
+    );
+    assert_eq!(
+        closed_stdout(&["-on", "1"], lines.as_bytes()),
+        (Some(0), String::new())
+    );
+    // as for all the other output
+    for args in [&["-c", "1"][..], &["-l", "1"], &["-A0", "1"]] {
+        assert_eq!(
+            closed_stdout(args, lines.as_bytes()),
+            (Some(0), String::new()),
+            "{:?}",
+            args
+        );
+    }
+    assert_eq!(closed_stdout(&["a"], b"a\0\n"), (Some(0), String::new()));
+    assert_eq!(closed_stdout(&["-rl", "a"], b""), (Some(0), String::new()));
+}
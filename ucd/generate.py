@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""Generates src/unicode/tables.rs from DerivedGeneralCategory.txt and
+Scripts.txt in this directory.
+
+Usage: python3 ucd/generate.py
+"""
+
+import os
+from collections import defaultdict
+
+RANGES_PER_LINE = 4
+
+
+def parse(path):
+    """Returns {value: [(first, last)]} from a UCD file of `range ; value` lines."""
+    table = defaultdict(list)
+    with open(path) as f:
+        for line in f:
+            line = line.split("#", 1)[0].strip()
+            if not line:
+                continue
+            code_points, value = (field.strip() for field in line.split(";"))
+            first, _, last = code_points.partition("..")
+            table[value].append((int(first, 16), int(last or first, 16)))
+    return table
+
+
+def char(cp):
+    return "'\\u{%x}'" % cp
+
+
+def write_table(out, name, table):
+    out.append("#[rustfmt::skip]")
+    out.append("pub const %s: &[(&str, &[(char, char)])] = &[" % name)
+    for value in sorted(table):
+        # surrogates are not chars
+        ranges = [(first, last) for first, last in sorted(table[value]) if first < 0xD800 or first > 0xDFFF]
+        if not ranges:
+            continue
+        out.append('    ("%s", &[' % value)
+        for i in range(0, len(ranges), RANGES_PER_LINE):
+            chunk = ranges[i : i + RANGES_PER_LINE]
+            out.append("        " + " ".join("(%s, %s)," % (char(a), char(b)) for a, b in chunk))
+        out.append("    ]),")
+    out.append("];")
+
+
+def main():
+    here = os.path.dirname(os.path.abspath(__file__))
+    out = [
+        "// Generated by ucd/generate.py from ucd/DerivedGeneralCategory.txt and",
+        "// ucd/Scripts.txt. Do not edit by hand.",
+        "",
+        "// (value, ranges) pairs, sorted by value",
+    ]
+    write_table(out, "GENERAL_CATEGORY", parse(os.path.join(here, "DerivedGeneralCategory.txt")))
+    out.append("")
+    out.append("// (value, ranges) pairs, sorted by value")
+    write_table(out, "SCRIPT", parse(os.path.join(here, "Scripts.txt")))
+    path = os.path.join(here, "..", "src", "unicode", "tables.rs")
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, "w") as f:
+        f.write("\n".join(out) + "\n")
+
+
+if __name__ == "__main__":
+    main()